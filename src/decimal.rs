@@ -0,0 +1,1128 @@
+use std::{cmp::Ordering, error::Error, fmt::{Debug, Display}, hash::{Hash, Hasher}, ops::Neg, str::FromStr};
+
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+const GUARD_DIGITS: usize = 10;
+/// The largest magnitude of the exponent of a decimal's leading digit, which leaves room to do
+/// arithmetic on exponents in `i64`.
+const MAX_EXPONENT: u64 = 999_999_999_999_999_999;
+/// The largest exponent of an angle's leading digit that trigonometric functions reduce, since
+/// reducing it takes that many extra digits of π.
+const MAX_ANGLE_EXPONENT: i64 = 1_000;
+/// The most digits `to_fixed` writes, which keeps huge exponents from being spelled out in full.
+pub const MAX_FIXED_DIGITS: usize = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Natural(Vec<u32>);
+impl Natural {
+    fn zero() -> Self {
+        Self(Vec::new())
+    }
+    fn from_u64(mut num: u64) -> Self {
+        let mut limbs = Vec::new();
+        while num > 0 {
+            limbs.push((num % BASE) as u32);
+            num /= BASE;
+        }
+        Self(limbs)
+    }
+    fn from_digits(digits: &str) -> Self {
+        let mut limbs = Vec::new();
+        let mut end = digits.len();
+        while end > 0 {
+            let start = end.saturating_sub(BASE_DIGITS);
+            limbs.push(digits[start..end].parse().unwrap());
+            end = start;
+        }
+        Self(limbs).trim()
+    }
+    fn pow10(exponent: usize) -> Self {
+        let mut limbs = vec![0; exponent / BASE_DIGITS];
+        limbs.push(10u32.pow((exponent % BASE_DIGITS) as u32));
+        Self(limbs)
+    }
+    fn pow(base: u32, mut exponent: u32) -> Self {
+        let mut base = Self::from_u64(base as u64);
+        let mut result = Self::from_u64(1);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            base = base.mul(&base);
+            exponent >>= 1;
+        }
+        result
+    }
+    fn trim(mut self) -> Self {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+        self
+    }
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+    fn is_odd(&self) -> bool {
+        self.0.first().is_some_and(|limb| limb % 2 == 1)
+    }
+    fn digits(&self) -> usize {
+        match self.0.last() {
+            Some(last) => (self.0.len() - 1) * BASE_DIGITS + last.ilog10() as usize + 1,
+            None => 0,
+        }
+    }
+    fn trailing_zeros(&self) -> usize {
+        match self.0.iter().position(|&limb| limb != 0) {
+            Some(index) => {
+                let mut limb = self.0[index];
+                let mut zeros = index * BASE_DIGITS;
+                while limb.is_multiple_of(10) {
+                    limb /= 10;
+                    zeros += 1;
+                }
+                zeros
+            },
+            None => 0,
+        }
+    }
+    fn to_u64(&self) -> Option<u64> {
+        self.0.iter().rev().try_fold(0u64, |num, &limb| num.checked_mul(BASE)?.checked_add(limb as u64))
+    }
+    fn add(&self, other: &Self) -> Self {
+        let mut limbs = Vec::with_capacity(self.0.len().max(other.0.len()) + 1);
+        let mut carry = 0;
+        for i in 0..self.0.len().max(other.0.len()) {
+            let sum = *self.0.get(i).unwrap_or(&0) as u64 + *other.0.get(i).unwrap_or(&0) as u64 + carry;
+            limbs.push((sum % BASE) as u32);
+            carry = sum / BASE;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        Self(limbs)
+    }
+    fn add_small(&self, small: u32) -> Self {
+        self.add(&Self::from_u64(small as u64))
+    }
+    fn sub(&self, other: &Self) -> Self {
+        let mut limbs = Vec::with_capacity(self.0.len());
+        let mut borrow = 0;
+        for i in 0..self.0.len() {
+            let mut difference = self.0[i] as i64 - *other.0.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = 0;
+            if difference < 0 {
+                difference += BASE as i64;
+                borrow = 1;
+            }
+            limbs.push(difference as u32);
+        }
+        Self(limbs).trim()
+    }
+    fn mul(&self, other: &Self) -> Self {
+        if self.is_zero() || other.is_zero() {
+            return Self::zero();
+        }
+        let mut limbs = vec![0u64; self.0.len() + other.0.len()];
+        for (i, &limb1) in self.0.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &limb2) in other.0.iter().enumerate() {
+                let product = limbs[i + j] + limb1 as u64 * limb2 as u64 + carry;
+                limbs[i + j] = product % BASE;
+                carry = product / BASE;
+            }
+            limbs[i + other.0.len()] += carry;
+        }
+        Self(limbs.into_iter().map(|limb| limb as u32).collect()).trim()
+    }
+    fn mul_small(&self, small: u32) -> Self {
+        let mut limbs = Vec::with_capacity(self.0.len() + 1);
+        let mut carry = 0;
+        for &limb in &self.0 {
+            let product = limb as u64 * small as u64 + carry;
+            limbs.push((product % BASE) as u32);
+            carry = product / BASE;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        Self(limbs).trim()
+    }
+    fn div_rem_small(&self, small: u32) -> (Self, u32) {
+        let mut limbs = vec![0; self.0.len()];
+        let mut remainder = 0;
+        for (i, &limb) in self.0.iter().enumerate().rev() {
+            let dividend = remainder * BASE + limb as u64;
+            limbs[i] = (dividend / small as u64) as u32;
+            remainder = dividend % small as u64;
+        }
+        (Self(limbs).trim(), remainder as u32)
+    }
+    fn div_rem(&self, other: &Self) -> (Self, Self) {
+        if self < other {
+            return (Self::zero(), self.clone());
+        }
+        if other.0.len() == 1 {
+            let (quotient, remainder) = self.div_rem_small(other.0[0]);
+            return (quotient, Self::from_u64(remainder as u64));
+        }
+        let factor = (BASE / (*other.0.last().unwrap() as u64 + 1)) as u32;
+        let mut u = self.mul_small(factor).0;
+        let v = other.mul_small(factor).0;
+        let n = v.len();
+        u.resize(self.0.len() + 2, 0);
+        let m = u.len() - n - 1;
+        let mut quotient = vec![0; m];
+        for j in (0..m).rev() {
+            let dividend = u[j + n] as u128 * BASE as u128 + u[j + n - 1] as u128;
+            let mut estimate = dividend / v[n - 1] as u128;
+            let mut remainder = dividend % v[n - 1] as u128;
+            while estimate >= BASE as u128 || estimate * v[n - 2] as u128 > remainder * BASE as u128 + u[j + n - 2] as u128 {
+                estimate -= 1;
+                remainder += v[n - 1] as u128;
+                if remainder >= BASE as u128 {
+                    break;
+                }
+            }
+            let mut estimate = estimate as u64;
+            let mut borrow = 0;
+            let mut carry = 0;
+            for i in 0..n {
+                let product = estimate * v[i] as u64 + carry;
+                carry = product / BASE;
+                let difference = u[i + j] as i64 - borrow - (product % BASE) as i64;
+                borrow = (difference < 0) as i64;
+                u[i + j] = (difference + borrow * BASE as i64) as u32;
+            }
+            let difference = u[j + n] as i64 - borrow - carry as i64;
+            if difference < 0 {
+                u[j + n] = (difference + BASE as i64) as u32;
+                estimate -= 1;
+                let mut carry = 0;
+                for i in 0..n {
+                    let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                    u[i + j] = (sum % BASE) as u32;
+                    carry = sum / BASE;
+                }
+                u[j + n] = ((u[j + n] as u64 + carry) % BASE) as u32;
+            } else {
+                u[j + n] = difference as u32;
+            }
+            quotient[j] = estimate as u32;
+        }
+        u.truncate(n);
+        let (remainder, _) = Self(u).trim().div_rem_small(factor);
+        (Self(quotient).trim(), remainder)
+    }
+    fn shift(&self, digits: usize) -> Self {
+        if self.is_zero() {
+            return Self::zero();
+        }
+        let mut limbs = vec![0; digits / BASE_DIGITS];
+        limbs.extend(self.mul_small(10u32.pow((digits % BASE_DIGITS) as u32)).0);
+        Self(limbs)
+    }
+    /// `self * 10^digits % modulus`, without building the shifted number when `digits` is large.
+    fn shift_rem(&self, digits: u64, modulus: &Self) -> Self {
+        let mut result = self.div_rem(modulus).1;
+        let mut power = Self::from_u64(10).div_rem(modulus).1;
+        let mut remaining = digits;
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                result = result.mul(&power).div_rem(modulus).1;
+            }
+            remaining >>= 1;
+            if remaining > 0 {
+                power = power.mul(&power).div_rem(modulus).1;
+            }
+        }
+        result
+    }
+    fn split(&self, digits: usize) -> (Self, Self) {
+        let index = digits / BASE_DIGITS;
+        if index >= self.0.len() {
+            return (Self::zero(), self.clone());
+        }
+        let (high, remainder) = Self(self.0[index..].to_vec()).div_rem_small(10u32.pow((digits % BASE_DIGITS) as u32));
+        let mut low = self.0[..index].to_vec();
+        low.push(remainder);
+        (high, Self(low).trim())
+    }
+    fn isqrt(&self) -> Self {
+        if self.is_zero() {
+            return Self::zero();
+        }
+        let mut root = Self::pow10(self.digits().div_ceil(2));
+        loop {
+            let next = root.add(&self.div_rem(&root).0).div_rem_small(2).0;
+            if next >= root {
+                return root;
+            }
+            root = next;
+        }
+    }
+}
+impl PartialOrd for Natural {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Natural {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.len().cmp(&other.0.len()).then_with(|| self.0.iter().rev().cmp(other.0.iter().rev()))
+    }
+}
+impl Display for Natural {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut iter = self.0.iter().rev();
+        match iter.next() {
+            Some(limb) => write!(f, "{}", limb)?,
+            None => write!(f, "0")?,
+        }
+        for limb in iter {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    Up,
+    Down,
+    Ceiling,
+    Floor,
+}
+
+/// The number of significant digits results are rounded to, and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    pub precision: usize,
+    pub rounding: Rounding,
+}
+impl Context {
+    pub fn new(precision: usize, rounding: Rounding) -> Self {
+        Self {
+            precision: precision.max(1),
+            rounding,
+        }
+    }
+}
+impl Default for Context {
+    fn default() -> Self {
+        Self::new(34, Rounding::HalfEven)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError;
+impl Display for ParseDecimalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid decimal literal")
+    }
+}
+impl Error for ParseDecimalError {}
+
+/// A result whose exponent is out of range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overflow;
+impl Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "decimal exponent out of range")
+    }
+}
+impl Error for Overflow {}
+
+/// The coefficient rounded to the precision of the context, and the number of digits dropped.
+fn round_to_precision(coefficient: Natural, negative: bool, context: Context) -> (Natural, usize) {
+    let digits = coefficient.digits();
+    if digits > context.precision {
+        let dropped = digits - context.precision;
+        (round_coefficient(&coefficient, dropped, negative, context.rounding), dropped)
+    } else {
+        (coefficient, 0)
+    }
+}
+fn round_coefficient(coefficient: &Natural, dropped: usize, negative: bool, rounding: Rounding) -> Natural {
+    let (quotient, half, inexact) = if dropped > coefficient.digits() {
+        (Natural::zero(), Ordering::Less, !coefficient.is_zero())
+    } else {
+        let (quotient, remainder) = coefficient.split(dropped);
+        let half = remainder.mul_small(2).cmp(&Natural::pow10(dropped));
+        (quotient, half, !remainder.is_zero())
+    };
+    let increment = match rounding {
+        Rounding::HalfEven => half == Ordering::Greater || (half == Ordering::Equal && quotient.is_odd()),
+        Rounding::HalfUp => half != Ordering::Less,
+        Rounding::HalfDown => half == Ordering::Greater,
+        Rounding::Up => inexact,
+        Rounding::Down => false,
+        Rounding::Ceiling => inexact && !negative,
+        Rounding::Floor => inexact && negative,
+    };
+    if increment {
+        quotient.add_small(1)
+    } else {
+        quotient
+    }
+}
+
+/// A decimal floating-point number carrying the context its results are rounded with.
+///
+/// Binary operations round to the context of their left operand.
+#[derive(Clone)]
+pub struct Decimal {
+    negative: bool,
+    coefficient: Natural,
+    exponent: i64,
+    context: Context,
+}
+impl Decimal {
+    fn new(negative: bool, coefficient: Natural, exponent: i64, context: Context) -> Option<Self> {
+        let (coefficient, dropped) = round_to_precision(coefficient, negative, context);
+        Self::exact(negative, coefficient, exponent.checked_add(dropped as i64)?, context)
+    }
+    /// The number with the trailing zeros of its coefficient moved into the exponent, or `None` if
+    /// that is out of range.
+    fn exact(negative: bool, coefficient: Natural, exponent: i64, context: Context) -> Option<Self> {
+        if coefficient.is_zero() {
+            return Some(Self::zero(context));
+        }
+        let zeros = coefficient.trailing_zeros();
+        let coefficient = coefficient.split(zeros).0;
+        let exponent = exponent.checked_add(zeros as i64)?;
+        if exponent.checked_add(coefficient.digits() as i64 - 1)?.unsigned_abs() > MAX_EXPONENT {
+            return None;
+        }
+        Some(Self {
+            negative,
+            coefficient,
+            exponent,
+            context,
+        })
+    }
+    pub fn zero(context: Context) -> Self {
+        Self {
+            negative: false,
+            coefficient: Natural::zero(),
+            exponent: 0,
+            context,
+        }
+    }
+    pub fn from_i64(num: i64, context: Context) -> Self {
+        let (coefficient, dropped) = round_to_precision(Natural::from_u64(num.unsigned_abs()), num < 0, context);
+        let zeros = coefficient.trailing_zeros();
+        Self {
+            negative: num < 0 && !coefficient.is_zero(),
+            coefficient: coefficient.split(zeros).0,
+            exponent: (dropped + zeros) as i64,
+            context,
+        }
+    }
+    pub fn from_f64(num: f64, context: Context) -> Option<Self> {
+        if !num.is_finite() {
+            return None;
+        }
+        let bits = num.to_bits();
+        let negative = bits >> 63 == 1;
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (mantissa, exponent) = if exponent == 0 {
+            (Natural::from_u64(fraction), -1074)
+        } else {
+            (Natural::from_u64(fraction | 1 << 52), exponent - 1075)
+        };
+        if exponent >= 0 {
+            Self::new(negative, mantissa.mul(&Natural::pow(2, exponent as u32)), 0, context)
+        } else {
+            Self::new(negative, mantissa.mul(&Natural::pow(5, (-exponent) as u32)), exponent, context)
+        }
+    }
+    pub fn pi(context: Context) -> Option<Self> {
+        let working = Context::new(context.precision + GUARD_DIGITS, Rounding::HalfEven);
+        let one = Self::from_i64(1, working);
+        let a = one.checked_div(&Self::from_i64(5, working))?.atan_series()?;
+        let b = one.checked_div(&Self::from_i64(239, working))?.atan_series()?;
+        Self::from_i64(16, working).checked_mul(&a)?.checked_sub(&Self::from_i64(4, working).checked_mul(&b)?)?.with_context(context)
+    }
+    pub fn context(&self) -> Context {
+        self.context
+    }
+    pub fn with_context(&self, context: Context) -> Option<Self> {
+        Self::new(self.negative, self.coefficient.clone(), self.exponent, context)
+    }
+    fn working(&self, extra: usize) -> Context {
+        Context::new(self.context.precision + GUARD_DIGITS + extra, Rounding::HalfEven)
+    }
+    fn adjusted(&self) -> i64 {
+        self.exponent + self.coefficient.digits().max(1) as i64 - 1
+    }
+    fn negligible(&self, sum: &Self) -> bool {
+        self.is_zero() || self.adjusted() < sum.adjusted() - sum.context.precision as i64 - 1
+    }
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+    pub fn to_i64(&self) -> Option<i64> {
+        if !self.is_integer() || self.adjusted() > 18 {
+            return None;
+        }
+        let magnitude = self.coefficient.shift(self.exponent as usize).to_u64()?;
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+    pub fn is_zero(&self) -> bool {
+        self.coefficient.is_zero()
+    }
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+    pub fn is_integer(&self) -> bool {
+        self.exponent >= 0
+    }
+    pub fn abs(&self) -> Self {
+        Self {
+            negative: false,
+            ..self.clone()
+        }
+    }
+    /// Rounds to a multiple of `10^exponent`, e.g. an exponent of `0` rounds to an integer.
+    pub fn quantize(&self, exponent: i64, rounding: Rounding) -> Option<Self> {
+        if self.exponent >= exponent {
+            return Some(self.clone());
+        }
+        let dropped = usize::try_from(exponent.checked_sub(self.exponent)?).ok()?;
+        let coefficient = round_coefficient(&self.coefficient, dropped, self.negative, rounding);
+        Self::exact(self.negative, coefficient, exponent, self.context)
+    }
+    /// Formats in plain notation with exactly `digits` digits after the point, unless that takes more
+    /// than `MAX_FIXED_DIGITS` digits.
+    pub fn to_fixed(&self, digits: usize) -> Option<String> {
+        if digits > MAX_FIXED_DIGITS || self.adjusted() > (MAX_FIXED_DIGITS - digits) as i64 {
+            return None;
+        }
+        let rounded = self.quantize(-i64::try_from(digits).ok()?, self.context.rounding)?;
+        let scaled = rounded.coefficient.shift((rounded.exponent + digits as i64) as usize).to_string();
+        let scaled = format!("{:0>width$}", scaled, width = digits + 1);
+        let (integer, fraction) = scaled.split_at(scaled.len() - digits);
+        let sign = if rounded.negative { "-" } else { "" };
+        Some(match digits {
+            0 => format!("{}{}", sign, integer),
+            _ => format!("{}{}.{}", sign, integer, fraction),
+        })
+    }
+    fn add_signed(&self, other: &Self, negative: bool) -> Option<Self> {
+        let context = self.context;
+        if other.is_zero() {
+            return self.with_context(context);
+        }
+        if self.is_zero() {
+            return Self::new(negative, other.coefficient.clone(), other.exponent, context);
+        }
+        let sticky = |large: &Self, small: &Self| {
+            let limit = large.exponent.min(large.adjusted().checked_sub(context.precision as i64 + 2)?).checked_sub(1)?;
+            if small.adjusted() < limit {
+                Some((Natural::from_u64(1), limit))
+            } else {
+                Some((small.coefficient.clone(), small.exponent))
+            }
+        };
+        let (coefficient1, exponent1) = sticky(other, self)?;
+        let (coefficient2, exponent2) = sticky(self, other)?;
+        let exponent = exponent1.min(exponent2);
+        let coefficient1 = coefficient1.shift((exponent1 - exponent) as usize);
+        let coefficient2 = coefficient2.shift((exponent2 - exponent) as usize);
+        if self.negative == negative {
+            Self::new(negative, coefficient1.add(&coefficient2), exponent, context)
+        } else if coefficient1 >= coefficient2 {
+            Self::new(self.negative, coefficient1.sub(&coefficient2), exponent, context)
+        } else {
+            Self::new(negative, coefficient2.sub(&coefficient1), exponent, context)
+        }
+    }
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        self.add_signed(other, other.negative)
+    }
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        self.add_signed(other, !other.negative)
+    }
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Self::new(self.negative != other.negative, self.coefficient.mul(&other.coefficient), self.exponent.checked_add(other.exponent)?, self.context)
+    }
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(Self::zero(self.context));
+        }
+        let shift = (self.context.precision + 1 + other.coefficient.digits()).saturating_sub(self.coefficient.digits());
+        let (mut quotient, remainder) = self.coefficient.shift(shift).div_rem(&other.coefficient);
+        let mut exponent = self.exponent.checked_sub(other.exponent)?.checked_sub(shift as i64)?;
+        if !remainder.is_zero() {
+            quotient = quotient.mul_small(10).add_small(1);
+            exponent -= 1;
+        }
+        Self::new(self.negative != other.negative, quotient, exponent, self.context)
+    }
+    /// The remainder of truncated division, taking the sign of the dividend like `f64`'s `%`.
+    pub fn checked_rem(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        if self.abs() < other.abs() {
+            return self.with_context(self.context);
+        }
+        let exponent = self.exponent.min(other.exponent);
+        let divisor = other.coefficient.shift((other.exponent - exponent) as usize);
+        let remainder = self.coefficient.shift_rem((self.exponent - exponent) as u64, &divisor);
+        Self::new(self.negative, remainder, exponent, self.context)
+    }
+    pub fn checked_pow(&self, exponent: &Self) -> Option<Self> {
+        if exponent.is_zero() {
+            return Some(Self::from_i64(1, self.context));
+        }
+        if self.is_zero() {
+            return if exponent.negative { None } else { Some(Self::zero(self.context)) };
+        }
+        if let Some(power) = exponent.to_i64().filter(|power| power.unsigned_abs() <= 1 << 20) {
+            let working = self.working(8);
+            let mut base = self.with_context(working)?;
+            let mut result = Self::from_i64(1, working);
+            let mut remaining = power.unsigned_abs();
+            while remaining > 0 {
+                if remaining & 1 == 1 {
+                    result = result.checked_mul(&base)?;
+                }
+                remaining >>= 1;
+                if remaining > 0 {
+                    base = base.checked_mul(&base)?;
+                }
+            }
+            if power < 0 {
+                result = Self::from_i64(1, working).checked_div(&result)?;
+            }
+            return result.with_context(self.context);
+        }
+        if self.negative {
+            return None;
+        }
+        let mut working = self.working(0);
+        let mut product = exponent.with_context(working)?.checked_mul(&self.with_context(working)?.ln()?)?;
+        if product.adjusted() > 17 {
+            return None;
+        }
+        if product.adjusted() > 0 {
+            working = self.working(product.adjusted() as usize);
+            product = exponent.with_context(working)?.checked_mul(&self.with_context(working)?.ln()?)?;
+        }
+        product.exp()?.with_context(self.context)
+    }
+    pub fn sqrt(&self) -> Option<Self> {
+        if self.negative {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(self.clone());
+        }
+        let mut shift = (2 * (self.context.precision + 1)).saturating_sub(self.coefficient.digits());
+        if (self.exponent - shift as i64).rem_euclid(2) != 0 {
+            shift += 1;
+        }
+        let scaled = self.coefficient.shift(shift);
+        let mut root = scaled.isqrt();
+        let mut exponent = (self.exponent - shift as i64).div_euclid(2);
+        if root.mul(&root) != scaled {
+            root = root.mul_small(10).add_small(1);
+            exponent -= 1;
+        }
+        Self::new(false, root, exponent, self.context)
+    }
+    pub fn cbrt(&self) -> Option<Self> {
+        if self.is_zero() {
+            return Some(self.clone());
+        }
+        let working = self.working(0);
+        let scale = self.adjusted().div_euclid(3);
+        let x = Self {
+            negative: false,
+            exponent: self.exponent - 3 * scale,
+            context: working,
+            ..self.clone()
+        };
+        let three = Self::from_i64(3, working);
+        let mut y = x.ln()?.checked_div(&three)?.exp()?;
+        for _ in 0..2 {
+            let square = y.checked_mul(&y)?;
+            y = y.checked_sub(&square.checked_mul(&y)?.checked_sub(&x)?.checked_div(&three.checked_mul(&square)?)?)?;
+        }
+        let root = Self {
+            negative: self.negative,
+            exponent: y.exponent + scale,
+            ..y
+        };
+        root.with_context(self.context)
+    }
+    pub fn exp(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(Self::from_i64(1, self.context));
+        }
+        if self.adjusted() > 17 {
+            return None;
+        }
+        let halvings = self.abs().to_f64().log2().max(0.0).ceil() as u32 + 8;
+        let working = self.working(halvings as usize * 3 / 10 + 1);
+        let reduced = self.with_context(working)?.checked_div(&Self::new(false, Natural::pow(2, halvings), 0, working)?)?;
+        let mut sum = Self::from_i64(1, working);
+        let mut term = sum.clone();
+        let mut n = 0;
+        loop {
+            n += 1;
+            term = term.checked_mul(&reduced)?.checked_div(&Self::from_i64(n, working))?;
+            if term.negligible(&sum) {
+                break;
+            }
+            sum = sum.checked_add(&term)?;
+        }
+        for _ in 0..halvings {
+            sum = sum.checked_mul(&sum)?;
+        }
+        sum.with_context(self.context)
+    }
+    pub fn ln(&self) -> Option<Self> {
+        if self.negative || self.is_zero() {
+            return None;
+        }
+        let adjusted = self.adjusted();
+        if adjusted.unsigned_abs() > 1 {
+            let working = self.working(adjusted.unsigned_abs().ilog10() as usize + 1);
+            let mantissa = Self {
+                exponent: self.exponent - adjusted,
+                context: working,
+                ..self.clone()
+            };
+            let scale = Self::from_i64(adjusted, working).checked_mul(&Self::from_i64(10, working).ln()?)?;
+            return mantissa.ln()?.checked_add(&scale)?.with_context(self.context);
+        }
+        let one = Self::from_i64(1, self.context);
+        let distance = self.checked_sub(&one)?;
+        if distance.is_zero() {
+            return Some(Self::zero(self.context));
+        }
+        let working = self.working((-distance.adjusted()).max(0) as usize);
+        let x = self.with_context(working)?;
+        let mantissa = Self {
+            exponent: 1 - self.coefficient.digits() as i64,
+            ..self.clone()
+        };
+        let estimate = mantissa.to_f64().ln() + self.adjusted() as f64 * std::f64::consts::LN_10;
+        let mut y = Self::from_f64(estimate, working)?;
+        let two = Self::from_i64(2, working);
+        for _ in 0..100 {
+            let power = y.exp()?;
+            let delta = two.checked_mul(&x.checked_sub(&power)?)?.checked_div(&x.checked_add(&power)?)?;
+            y = y.checked_add(&delta)?;
+            if delta.is_zero() || delta.adjusted() < y.adjusted() - working.precision as i64 {
+                break;
+            }
+        }
+        y.with_context(self.context)
+    }
+    /// The length of the hypotenuse of a right triangle with the two legs.
+    pub fn hypot(&self, other: &Self) -> Option<Self> {
+        self.checked_mul(self)?.checked_add(&other.checked_mul(other)?)?.sqrt()
+    }
+    /// The logarithm to the given base.
+    pub fn log(&self, base: &Self) -> Option<Self> {
+        let working = self.working(0);
+        let result = self.with_context(working)?.ln()?.checked_div(&base.with_context(working)?.ln()?)?;
+        result.with_context(self.context)
+    }
+    /// Whether the argument is small enough that an odd function like `sinh`, being `x + O(x^3)`,
+    /// rounds to it, and `exp` and even functions like `cosh` round to 1.
+    fn is_negligible(&self) -> bool {
+        self.is_zero() || self.adjusted() < -(self.context.precision as i64)
+    }
+    /// Working context with enough extra digits to absorb the cancellation of `exp(x) - exp(-x)`
+    /// and the like near zero.
+    fn cancelling(&self) -> Context {
+        self.working((-self.adjusted()).max(0) as usize)
+    }
+    pub fn sinh(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let working = self.cancelling();
+        let power = self.with_context(working)?.exp()?;
+        let difference = power.checked_sub(&Self::from_i64(1, working).checked_div(&power)?)?;
+        difference.checked_div(&Self::from_i64(2, working))?.with_context(self.context)
+    }
+    pub fn cosh(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(Self::from_i64(1, self.context));
+        }
+        let working = self.working(0);
+        let power = self.with_context(working)?.exp()?;
+        let sum = power.checked_add(&Self::from_i64(1, working).checked_div(&power)?)?;
+        sum.checked_div(&Self::from_i64(2, working))?.with_context(self.context)
+    }
+    pub fn tanh(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let working = self.cancelling();
+        let one = Self::from_i64(1, working);
+        let result = if self.abs() > Self::from_i64(2 * working.precision as i64, working) {
+            one
+        } else {
+            let power = self.with_context(working)?.checked_mul(&Self::from_i64(2, working))?.abs().exp()?;
+            power.checked_sub(&one)?.checked_div(&power.checked_add(&one)?)?
+        };
+        let result = if self.negative { -result } else { result };
+        result.with_context(self.context)
+    }
+    pub fn asinh(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let working = self.cancelling();
+        let x = self.abs().with_context(working)?;
+        let result = if x.adjusted() > working.precision as i64 {
+            x.checked_mul(&Self::from_i64(2, working))?.ln()?
+        } else {
+            x.checked_add(&x.checked_mul(&x)?.checked_add(&Self::from_i64(1, working))?.sqrt()?)?.ln()?
+        };
+        let result = if self.negative { -result } else { result };
+        result.with_context(self.context)
+    }
+    pub fn acosh(&self) -> Option<Self> {
+        // `x^2 - 1` cancels near 1 as `x - 1` does near zero, so factor it and carry the digits lost.
+        let working = self.checked_sub(&Self::from_i64(1, self.context))?.cancelling();
+        let one = Self::from_i64(1, working);
+        let x = self.with_context(working)?;
+        let root = x.checked_sub(&one)?.checked_mul(&x.checked_add(&one)?)?.sqrt()?;
+        x.checked_add(&root)?.ln()?.with_context(self.context)
+    }
+    pub fn atanh(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let working = self.cancelling();
+        let one = Self::from_i64(1, working);
+        let x = self.with_context(working)?;
+        let ratio = one.checked_add(&x)?.checked_div(&one.checked_sub(&x)?)?;
+        ratio.ln()?.checked_div(&Self::from_i64(2, working))?.with_context(self.context)
+    }
+    fn reduce_angle(&self, context: Context) -> Option<Self> {
+        if self.adjusted() > MAX_ANGLE_EXPONENT {
+            return None;
+        }
+        let x = self.with_context(context)?;
+        let tau = Self::pi(context)?.checked_mul(&Self::from_i64(2, context))?;
+        let turns = x.checked_div(&tau)?.quantize(0, Rounding::HalfEven)?;
+        x.checked_sub(&turns.checked_mul(&tau)?)
+    }
+    pub fn sin(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let working = self.working(self.adjusted().max(0) as usize);
+        let x = self.reduce_angle(working)?;
+        let square = x.checked_mul(&x)?;
+        let mut sum = x.clone();
+        let mut term = x;
+        let mut n = 1;
+        loop {
+            term = -term.checked_mul(&square)?.checked_div(&Self::from_i64((n + 1) * (n + 2), working))?;
+            n += 2;
+            if term.negligible(&sum) {
+                break;
+            }
+            sum = sum.checked_add(&term)?;
+        }
+        sum.with_context(self.context)
+    }
+    pub fn cos(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(Self::from_i64(1, self.context));
+        }
+        let working = self.working(self.adjusted().max(0) as usize);
+        let x = self.reduce_angle(working)?;
+        let square = x.checked_mul(&x)?;
+        let mut sum = Self::from_i64(1, working);
+        let mut term = sum.clone();
+        let mut n = 0;
+        loop {
+            term = -term.checked_mul(&square)?.checked_div(&Self::from_i64((n + 1) * (n + 2), working))?;
+            n += 2;
+            if term.negligible(&sum) {
+                break;
+            }
+            sum = sum.checked_add(&term)?;
+        }
+        sum.with_context(self.context)
+    }
+    pub fn tan(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let x = self.with_context(self.working(0))?;
+        x.sin()?.checked_div(&x.cos()?)?.with_context(self.context)
+    }
+    fn atan_series(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let square = self.checked_mul(self)?;
+        let mut sum = self.clone();
+        let mut power = self.clone();
+        let mut n = 1;
+        loop {
+            power = -power.checked_mul(&square)?;
+            n += 2;
+            let term = power.checked_div(&Self::from_i64(n, self.context))?;
+            if term.negligible(&sum) {
+                break;
+            }
+            sum = sum.checked_add(&term)?;
+        }
+        Some(sum)
+    }
+    pub fn atan(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let working = self.working(0);
+        let one = Self::from_i64(1, working);
+        let tenth = Self::exact(false, Natural::from_u64(1), -1, working)?;
+        let mut x = self.abs().with_context(working)?;
+        let inverted = x > one;
+        if inverted {
+            x = one.checked_div(&x)?;
+        }
+        let mut halvings = 0;
+        while x > tenth {
+            x = x.checked_div(&one.checked_add(&one.checked_add(&x.checked_mul(&x)?)?.sqrt()?)?)?;
+            halvings += 1;
+        }
+        let mut result = x.atan_series()?.checked_mul(&Self::new(false, Natural::pow(2, halvings), 0, working)?)?;
+        if inverted {
+            result = Self::pi(working)?.checked_div(&Self::from_i64(2, working))?.checked_sub(&result)?;
+        }
+        if self.negative {
+            result = -result;
+        }
+        result.with_context(self.context)
+    }
+    /// The angle of the point `(x, self)` in `(-π, π]`.
+    pub fn atan2(&self, x: &Self) -> Option<Self> {
+        let working = self.working(0);
+        let pi = Self::pi(working)?;
+        let result = if x.is_zero() {
+            match (self.is_zero(), self.negative) {
+                (true, _) => Self::zero(working),
+                (false, negative) => {
+                    let result = pi.checked_div(&Self::from_i64(2, working))?;
+                    if negative { -result } else { result }
+                },
+            }
+        } else {
+            let angle = self.with_context(working)?.checked_div(&x.with_context(working)?)?.atan()?;
+            match (x.negative, self.negative) {
+                (false, _) => angle,
+                (true, false) => angle.checked_add(&pi)?,
+                (true, true) => angle.checked_sub(&pi)?,
+            }
+        };
+        result.with_context(self.context)
+    }
+    pub fn asin(&self) -> Option<Self> {
+        if self.is_negligible() {
+            return Some(self.clone());
+        }
+        let working = self.working(0);
+        let one = Self::from_i64(1, working);
+        let x = self.with_context(working)?;
+        let result = match x.abs().cmp(&one) {
+            Ordering::Greater => return None,
+            Ordering::Equal => {
+                let result = Self::pi(working)?.checked_div(&Self::from_i64(2, working))?;
+                if self.negative { -result } else { result }
+            },
+            Ordering::Less => x.checked_div(&one.checked_sub(&x.checked_mul(&x)?)?.sqrt()?)?.atan()?,
+        };
+        result.with_context(self.context)
+    }
+    pub fn acos(&self) -> Option<Self> {
+        let working = self.working(0);
+        let result = Self::pi(working)?.checked_div(&Self::from_i64(2, working))?.checked_sub(&self.with_context(working)?.asin()?)?;
+        result.with_context(self.context)
+    }
+}
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Decimal {}
+impl Hash for Decimal {
+    /// Equal numbers have the same sign, coefficient and exponent, since trailing zeros are always
+    /// moved into the exponent.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.negative.hash(state);
+        self.coefficient.hash(state);
+        self.exponent.hash(state);
+    }
+}
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let magnitude = match (self.is_zero(), other.is_zero()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return if other.negative { Ordering::Greater } else { Ordering::Less },
+            (false, true) => return if self.negative { Ordering::Less } else { Ordering::Greater },
+            (false, false) => self.adjusted().cmp(&other.adjusted()).then_with(|| {
+                let exponent = self.exponent.min(other.exponent);
+                self.coefficient.shift((self.exponent - exponent) as usize)
+                    .cmp(&other.coefficient.shift((other.exponent - exponent) as usize))
+            }),
+        };
+        match (self.negative, other.negative) {
+            (false, false) => magnitude,
+            (true, true) => magnitude.reverse(),
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+        }
+    }
+}
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal {
+            negative: !self.negative && !self.is_zero(),
+            ..self
+        }
+    }
+}
+impl Neg for &Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        -self.clone()
+    }
+}
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    /// Parses a decimal literal exactly, without rounding it to the default context.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (negative, string) = match string.strip_prefix('-') {
+            Some(string) => (true, string),
+            None => (false, string.strip_prefix('+').unwrap_or(string)),
+        };
+        let (mantissa, exponent) = match string.find(['e', 'E']) {
+            Some(index) => (&string[..index], string[index + 1..].parse::<i64>().map_err(|_| ParseDecimalError)?),
+            None => (string, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if integer.is_empty() && fraction.is_empty() || !integer.chars().chain(fraction.chars()).all(|ch| ch.is_ascii_digit()) {
+            return Err(ParseDecimalError);
+        }
+        let coefficient = Natural::from_digits(&format!("{}{}", integer, fraction));
+        let exponent = exponent.checked_sub(fraction.len() as i64).ok_or(ParseDecimalError)?;
+        Self::exact(negative, coefficient, exponent, Context::default()).ok_or(ParseDecimalError)
+    }
+}
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = self.coefficient.to_string();
+        let adjusted = self.adjusted();
+        if self.negative {
+            write!(f, "-")?;
+        }
+        if self.exponent <= 0 && adjusted >= -6 {
+            let point = digits.len() as i64 + self.exponent;
+            if point > 0 {
+                let (integer, fraction) = digits.split_at(point as usize);
+                write!(f, "{}", integer)?;
+                if !fraction.is_empty() {
+                    write!(f, ".{}", fraction)?;
+                }
+                Ok(())
+            } else {
+                write!(f, "0.{}{}", "0".repeat((-point) as usize), digits)
+            }
+        } else if self.exponent > 0 && adjusted < self.context.precision as i64 {
+            write!(f, "{}{}", digits, "0".repeat(self.exponent as usize))
+        } else {
+            write!(f, "{}", &digits[..1])?;
+            if digits.len() > 1 {
+                write!(f, ".{}", &digits[1..])?;
+            }
+            write!(f, "E{}{}", if adjusted < 0 { '-' } else { '+' }, adjusted.abs())
+        }
+    }
+}
+impl Debug for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(string: &str, precision: usize) -> Decimal {
+        string.parse::<Decimal>().unwrap().with_context(Context::new(precision, Rounding::HalfEven)).unwrap()
+    }
+
+    #[test]
+    fn acosh_keeps_precision_near_one() {
+        let result = decimal("1.000000000000000000001", 30).acosh().unwrap();
+        assert_eq!(result.to_string(), "4.47213595499957939281797465947E-11");
+        assert_eq!(decimal("1", 30).acosh().unwrap().to_string(), "0");
+        assert!(decimal("0.5", 30).acosh().is_none());
+        assert_eq!(decimal("2", 20).acosh().unwrap().to_string(), "1.3169578969248167086");
+    }
+
+    #[test]
+    fn every_rounding_mode_rounds_to_precision() {
+        let cases = [
+            (Rounding::HalfEven, ["1.2", "1.4", "-1.2", "1.2", "-1.2"]),
+            (Rounding::HalfUp, ["1.3", "1.4", "-1.3", "1.2", "-1.2"]),
+            (Rounding::HalfDown, ["1.2", "1.3", "-1.2", "1.2", "-1.2"]),
+            (Rounding::Up, ["1.3", "1.4", "-1.3", "1.3", "-1.3"]),
+            (Rounding::Down, ["1.2", "1.3", "-1.2", "1.2", "-1.2"]),
+            (Rounding::Ceiling, ["1.3", "1.4", "-1.2", "1.3", "-1.2"]),
+            (Rounding::Floor, ["1.2", "1.3", "-1.3", "1.2", "-1.3"]),
+        ];
+        for (rounding, expected) in cases {
+            let context = Context::new(2, rounding);
+            let rounded = ["1.25", "1.35", "-1.25", "1.21", "-1.21"].map(|num| num.parse::<Decimal>().unwrap().with_context(context).unwrap().to_string());
+            assert_eq!(rounded, expected, "{:?}", rounding);
+        }
+    }
+
+    #[test]
+    fn arithmetic_is_exact_until_it_rounds() {
+        assert_eq!(decimal("0.1", 20).checked_add(&decimal("0.2", 20)).unwrap().to_string(), "0.3");
+        assert_eq!(decimal("1", 5).checked_div(&decimal("3", 5)).unwrap().to_string(), "0.33333");
+        assert_eq!(decimal("2", 5).checked_div(&decimal("3", 5)).unwrap().to_string(), "0.66667");
+        let down = Context::new(5, Rounding::Down);
+        assert_eq!(Decimal::from_i64(2, down).checked_div(&Decimal::from_i64(3, down)).unwrap().to_string(), "0.66666");
+        assert_eq!(decimal("2", 30).sqrt().unwrap().to_string(), "1.41421356237309504880168872421");
+        assert_eq!(Decimal::pi(Context::new(30, Rounding::HalfEven)).unwrap().to_string(), "3.14159265358979323846264338328");
+        assert!(decimal("1", 5).checked_div(&decimal("0", 5)).is_none());
+    }
+}
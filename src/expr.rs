@@ -1,34 +1,326 @@
-use crate::parse::*;
-use std::{collections::HashMap, error::Error, rc::Rc, fmt::{Debug, Display}};
+use crate::{decimal::{Context, Decimal, Overflow, Rounding, MAX_FIXED_DIGITS}, lifted::{Lifted, Operator}, matrix::Matrix, parse::*, range::{steps, Range}, register::{Register, TypeMismatch}};
+use std::{cell::{Cell, RefCell}, cmp::Ordering, collections::HashMap, error::Error, fs, ops::RangeInclusive, path::{Path, PathBuf}, rc::Rc, fmt::{Debug, Display}};
+
+pub type NativeFunction = dyn Fn(Vec<Value>) -> Result<Value, Box<dyn Error>>;
+pub type NativeForm = dyn for<'a> Fn(Vec<Thunk<'a>>) -> Result<Value, Box<dyn Error>>;
 
 #[derive(Clone)]
 pub enum Value {        
     Number(f64),
-    Function(Rc<dyn Fn(Vec<Value>) -> Result<Value, Box<dyn Error>>>),
+    Decimal(Decimal),
+    Boolean(bool),
+    String(Rc<str>),
+    List(Rc<[Value]>),
+    Tuple(Rc<[Value]>),
+    Record(Rc<Record>),
+    Matrix(Rc<Matrix>),
+    Range(Rc<Range>),
+    Function(Rc<Function>),
+    Form(Rc<Form>),
 }
 impl Debug for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Number(num) => write!(f, "Number({})", num),
-            Self::Function(_) => write!(f, "Function"),
+            Self::Decimal(num) => write!(f, "Decimal({})", num),
+            Self::Boolean(boolean) => write!(f, "Boolean({})", boolean),
+            Self::String(string) => write!(f, "String({:?})", string),
+            Self::List(items) => write!(f, "List({:?})", items),
+            Self::Tuple(items) => write!(f, "Tuple({:?})", items),
+            Self::Record(record) => write!(f, "Record({:?})", record),
+            Self::Matrix(matrix) => write!(f, "Matrix({})", matrix),
+            Self::Range(range) => write!(f, "Range({})", range),
+            Self::Function(func) => write!(f, "Function({})", func.signature()),
+            Self::Form(form) => write!(f, "Form({})", form.signature()),
         }
     }
 }
+impl Value {
+    pub fn record(value: impl IntoRecord) -> Self {
+        Self::Record(Rc::new(value.into_record()))
+    }
+    pub fn native(name: &str, params: &[&str], doc: &str, body: impl Fn(Vec<Value>) -> Result<Value, Box<dyn Error>> + 'static) -> Self {
+        Self::Function(Rc::new(Function::native(name, params, doc, body)))
+    }
+    pub fn function(body: impl Fn(Vec<Value>) -> Result<Value, Box<dyn Error>> + 'static) -> Self {
+        Self::Function(Rc::new(Function::new(body)))
+    }
+    pub fn form(name: &str, params: &[&str], doc: &str, body: impl for<'a> Fn(Vec<Thunk<'a>>) -> Result<Value, Box<dyn Error>> + 'static) -> Self {
+        Self::Form(Rc::new(Form::new(name, params, doc, body)))
+    }
+}
+
+/// A callable value along with what is known about it.
+pub struct Function {
+    pub name: Option<String>,
+    /// The accepted numbers of arguments, if known.
+    pub arity: Option<RangeInclusive<usize>>,
+    pub params: Option<Vec<String>>,
+    pub doc: Option<String>,
+    /// Whether calls depend only on their arguments, so that their results may be reused.
+    pub pure: bool,
+    pub derivative: Option<fn() -> Lifted>,
+    /// Whether calling with too few arguments returns a function awaiting the rest.
+    pub partial: bool,
+    pub body: Body,
+}
+pub enum Body {
+    Native(Box<NativeFunction>),
+    Lifted(Lifted),
+}
+impl Function {
+    /// An anonymous function that nothing is known about.
+    pub fn new(body: impl Fn(Vec<Value>) -> Result<Value, Box<dyn Error>> + 'static) -> Self {
+        Self {
+            name: None,
+            arity: None,
+            params: None,
+            doc: None,
+            pure: false,
+            derivative: None,
+            partial: false,
+            body: Body::Native(Box::new(body)),
+        }
+    }
+    /// A named, documented pure function. A parameter ending in `?` is optional and one ending in
+    /// `...` takes the rest of the arguments.
+    pub fn native(name: &str, params: &[&str], doc: &str, body: impl Fn(Vec<Value>) -> Result<Value, Box<dyn Error>> + 'static) -> Self {
+        let required = params.iter().filter(|param| !param.ends_with('?')).count();
+        let variadic = params.iter().any(|param| param.ends_with("..."));
+        Self {
+            name: Some(name.to_owned()),
+            arity: Some(required..=if variadic { usize::MAX } else { params.len() }),
+            params: Some(params.iter().map(|param| param.to_string()).collect()),
+            doc: Some(doc.to_owned()),
+            pure: true,
+            derivative: None,
+            partial: false,
+            body: Body::Native(Box::new(body)),
+        }
+    }
+    pub fn lifted(tree: Lifted) -> Self {
+        Self {
+            name: None,
+            arity: None,
+            params: None,
+            doc: None,
+            pure: tree.pure(),
+            derivative: None,
+            partial: false,
+            body: Body::Lifted(tree),
+        }
+    }
+    pub fn call(self: &Rc<Self>, args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+        if let Some(arity) = &self.arity {
+            if self.partial && !args.is_empty() && args.len() < *arity.start() {
+                return Ok(Value::Function(Rc::new(self.apply(args))));
+            }
+            if !arity.contains(&args.len()) {
+                return Err(Box::new(ArityMismatch { function: Some(self.signature()), expected: arity.clone(), found: args.len() }));
+            }
+        }
+        match &self.body {
+            Body::Native(body) => body(args).map_err(|err| attribute(err, || self.signature())),
+            Body::Lifted(tree) => tree.eval(&args),
+        }
+    }
+    /// The function awaiting the arguments after `args`, which itself accepts partial application.
+    pub fn apply(self: &Rc<Self>, args: Vec<Value>) -> Self {
+        let given = args.len();
+        let func = self.clone();
+        Self {
+            name: self.name.clone(),
+            arity: self.arity.as_ref().map(|arity| {
+                arity.start().saturating_sub(given)..=if *arity.end() == usize::MAX { usize::MAX } else { arity.end().saturating_sub(given) }
+            }),
+            params: self.params.as_ref().map(|params| {
+                let fixed = params.iter().filter(|param| !param.ends_with("...")).count();
+                params[given.min(fixed)..].to_vec()
+            }),
+            doc: self.doc.clone(),
+            pure: self.pure,
+            derivative: None,
+            partial: true,
+            body: Body::Native(Box::new(move |rest| {
+                let mut args = args.clone();
+                args.extend(rest);
+                func.call(args)
+            })),
+        }
+    }
+    /// The name followed by the parameters, such as `atan2(y, x)`, or the arithmetic of a lifted function.
+    pub fn signature(&self) -> String {
+        if let Body::Lifted(tree) = &self.body {
+            return tree.to_string();
+        }
+        let name = self.name.as_deref().unwrap_or("<anonymous>");
+        match &self.params {
+            Some(params) => format!("{}({})", name, params.join(", ")),
+            None => name.to_owned(),
+        }
+    }
+}
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.signature())?;
+        if let Some(doc) = &self.doc {
+            write!(f, ": {}", doc)?;
+        }
+        Ok(())
+    }
+}
+
+/// A built-in that receives its arguments unevaluated, such as `sum`, along with its documentation.
+pub struct Form {
+    pub name: String,
+    pub params: Vec<String>,
+    pub doc: String,
+    pub body: Box<NativeForm>,
+}
+impl Form {
+    pub fn new(name: &str, params: &[&str], doc: &str, body: impl for<'a> Fn(Vec<Thunk<'a>>) -> Result<Value, Box<dyn Error>> + 'static) -> Self {
+        Self {
+            name: name.to_owned(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            doc: doc.to_owned(),
+            body: Box::new(body),
+        }
+    }
+    pub fn call(&self, args: Vec<Thunk>) -> Result<Value, Box<dyn Error>> {
+        if args.len() != self.params.len() {
+            return Err(Box::new(ArityMismatch { function: Some(self.signature()), expected: self.params.len()..=self.params.len(), found: args.len() }));
+        }
+        (self.body)(args).map_err(|err| attribute(err, || self.signature()))
+    }
+    /// The name followed by the parameters, such as `sum(name = range, body)`.
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params.join(", "))
+    }
+}
+impl Display for Form {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.signature(), self.doc)
+    }
+}
+
+/// Names the callee in an [`InvalidArguments`], [`TypeMismatch`] or [`MalformedForm`] error that does
+/// not name one yet.
+fn attribute(mut err: Box<dyn Error>, signature: impl FnOnce() -> String) -> Box<dyn Error> {
+    let function = if let Some(err) = err.downcast_mut::<InvalidArguments>() {
+        &mut err.function
+    } else if let Some(err) = err.downcast_mut::<TypeMismatch>() {
+        &mut err.function
+    } else if let Some(err) = err.downcast_mut::<MalformedForm>() {
+        &mut err.form
+    } else {
+        return err;
+    };
+    function.get_or_insert_with(signature);
+    err
+}
+
+/// Named fields, kept in the order they were inserted.
+#[derive(Clone, Default)]
+pub struct Record(Vec<(String, Value)>);
+impl Record {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.iter().find(|(field, _)| field == name).map(|(_, value)| value)
+    }
+    pub fn insert(&mut self, name: impl Into<String>, value: Value) {
+        let name = name.into();
+        match self.0.iter_mut().find(|(field, _)| *field == name) {
+            Some((_, field)) => *field = value,
+            None => self.0.push((name, value)),
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value))
+    }
+}
+impl<K: Into<String>> FromIterator<(K, Value)> for Record {
+    fn from_iter<T: IntoIterator<Item = (K, Value)>>(iter: T) -> Self {
+        let mut record = Self::new();
+        for (name, value) in iter {
+            record.insert(name, value);
+        }
+        record
+    }
+}
+impl Debug for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        let mut iter = self.iter();
+        if let Some((name, value)) = iter.next() {
+            write!(f, "{}: {:?}", name, value)?;
+        }
+        for (name, value) in iter {
+            write!(f, ", {}: {:?}", name, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// A Rust type that can be handed to expressions as a [`Value::Record`].
+pub trait IntoRecord {
+    fn into_record(self) -> Record;
+}
+impl IntoRecord for Record {
+    fn into_record(self) -> Record {
+        self
+    }
+}
 
 #[derive(Debug)]
 pub enum ExpressionError {
     UndefinedIdentifier(String),
     FunctionCallFailure(Box<dyn Error>),
+    ExpectedBoolean(Value),
+    BindingFailure(String, Box<ExpressionError>),
+    InvalidIndex(Value, Value),
+    InvalidDestructuring(usize, Value),
+    UndefinedField(Value, String),
+    ExpectedRecord(Value),
+    ImportFailure(String, Box<dyn Error>),
+    InvalidRange(Vec<Value>),
+    UnexpectedBinding(String),
+    NoMatchingBranch(Span),
+    NoMatchingClause(String, Vec<Value>),
+    RecursionLimit(String),
 }
 impl Display for ExpressionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::UndefinedIdentifier(ident) => write!(f, "undefined identifier \"{}\"", ident),
-            Self::FunctionCallFailure(err) => write!(f, "function call failure {:?}", err),
+            Self::FunctionCallFailure(err) => write!(f, "function call failure: {}", err),
+            Self::ExpectedBoolean(value) => write!(f, "expected a boolean but found {:?}", value),
+            Self::BindingFailure(name, err) => write!(f, "binding failure \"{}\": {}", name, err),
+            Self::InvalidIndex(value, index) => write!(f, "invalid index {:?} into {:?}", index, value),
+            Self::InvalidDestructuring(len, value) => write!(f, "cannot destructure {:?} into {} names", value, len),
+            Self::UndefinedField(value, name) => write!(f, "undefined field \"{}\" in {:?}", name, value),
+            Self::ExpectedRecord(value) => write!(f, "expected a record but found {:?}", value),
+            Self::ImportFailure(path, err) => write!(f, "failed to import \"{}\": {}", path, err),
+            Self::InvalidRange(bounds) => write!(f, "invalid range with start, end and step {:?}", bounds),
+            Self::NoMatchingBranch(span) => write!(f, "no branch of the piecewise expression at {} matches", span),
+            Self::NoMatchingClause(name, args) => write!(f, "no clause of \"{}\" matches the arguments {:?}", name, args),
+            Self::RecursionLimit(name) => write!(f, "recursion limit exceeded in \"{}\"", name),
+            Self::UnexpectedBinding(name) => write!(f, "\"{} = ...\" is only allowed as an argument to a form such as sum", name),
         }
     }
 }
 impl Error for ExpressionError {}
+impl ExpressionError {
+    /// Wraps the error of a failed call, passing a recursion limit through so it is not wrapped once per call.
+    fn call_failure(err: Box<dyn Error>) -> Self {
+        match err.downcast::<Self>() {
+            Ok(err) if matches!(*err, Self::RecursionLimit(_)) => *err,
+            Ok(err) => Self::FunctionCallFailure(err),
+            Err(err) => Self::FunctionCallFailure(err),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct InvalidFunction(pub Value);
@@ -40,230 +332,1640 @@ impl Display for InvalidFunction {
 impl Error for InvalidFunction {}
 
 #[derive(Debug, Clone)]
-pub struct InvalidArguments(pub Vec<Value>);
+pub struct InvalidArguments {
+    pub function: Option<String>,
+    pub args: Vec<Value>,
+}
+impl InvalidArguments {
+    pub fn new(args: Vec<Value>) -> Self {
+        Self { function: None, args }
+    }
+}
 impl Display for InvalidArguments {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "invalid arguments {:?}", self.0)
+        write!(f, "invalid arguments {:?}", self.args)?;
+        if let Some(function) = &self.function {
+            write!(f, " to {}", function)?;
+        }
+        Ok(())
     }
 }
 impl Error for InvalidArguments {}
 
-fn plus(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
-    match args.as_slice() {
-        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1 + num2)),
-        [Value::Function(func1), Value::Function(func2)] => {
-            let func1 = func1.clone();
-            let func2 = func2.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                plus(vec![func1(args.clone())?, func2(args)?])
-            }))) 
+/// Arguments to a form that do not have the shape it expects, such as `sum` without a binding.
+#[derive(Debug, Clone, Default)]
+pub struct MalformedForm {
+    pub form: Option<String>,
+}
+impl Display for MalformedForm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.form {
+            Some(form) => write!(f, "expected a call of the form {}", form),
+            None => write!(f, "malformed arguments to a form"),
+        }
+    }
+}
+impl Error for MalformedForm {}
+
+/// Lists of decimals used as a matrix, which would have to be rounded to floats to become one.
+#[derive(Debug, Clone)]
+pub struct DecimalMatrix(pub Value);
+impl Display for DecimalMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "matrices of decimals are not supported, found {:?}", self.0)
+    }
+}
+impl Error for DecimalMatrix {}
+
+#[derive(Debug, Clone)]
+pub struct NoConvergence {
+    pub iterations: usize,
+    pub last: Value,
+}
+impl Display for NoConvergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no convergence after {} iterations, last at {:?}", self.iterations, self.last)
+    }
+}
+impl Error for NoConvergence {}
+
+#[derive(Debug, Clone)]
+pub struct ArityMismatch {
+    pub function: Option<String>,
+    pub expected: RangeInclusive<usize>,
+    pub found: usize,
+}
+impl Display for ArityMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(function) = &self.function {
+            write!(f, "{} ", function)?;
+        }
+        match (*self.expected.start(), *self.expected.end()) {
+            (min, max) if min == max => write!(f, "expected {} arguments but found {}", min, self.found),
+            (min, usize::MAX) => write!(f, "expected at least {} arguments but found {}", min, self.found),
+            (min, max) => write!(f, "expected {} to {} arguments but found {}", min, max, self.found),
+        }
+    }
+}
+impl Error for ArityMismatch {}
+
+pub(crate) fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(num) if num.fract() == 0.0 => Some(*num as i64),
+        Value::Decimal(num) => num.to_i64(),
+        _ => None,
+    }
+}
+fn position(index: &Value, len: usize) -> Option<i64> {
+    match integer(index)? {
+        index if index < 0 => index.checked_add(i64::try_from(len).ok()?),
+        index => Some(index),
+    }
+}
+fn index(value: &Value, index: &Value) -> Option<Value> {
+    match value {
+        Value::List(items) | Value::Tuple(items) => items.get(usize::try_from(position(index, items.len())?).ok()?).cloned(),
+        Value::Range(range) => range.get(usize::try_from(position(index, range.len())?).ok()?),
+        Value::Matrix(matrix) => {
+            let row = usize::try_from(position(index, matrix.rows())?).ok().filter(|&row| row < matrix.rows())?;
+            Some(vector(matrix.row(row)))
         },
-        [value, Value::Function(func)] => {
-            let value = value.clone();
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                plus(vec![value.clone(), func(args)?])
-            }))) 
+        _ => None,
+    }
+}
+fn field(value: &Value, name: &str) -> Option<Value> {
+    match value {
+        Value::Record(record) => record.get(name).cloned(),
+        _ => None,
+    }
+}
+fn slice(value: &Value, start: Option<&Value>, end: Option<&Value>) -> Result<Value, Value> {
+    let len = match value {
+        Value::List(items) => items.len(),
+        _ => return Err(start.or(end).cloned().unwrap_or(Value::List(Rc::from([])))),
+    };
+    let bound = |bound: Option<&Value>, default: usize| match bound {
+        Some(bound) => position(bound, len).map(|bound| bound.clamp(0, len as i64) as usize).ok_or_else(|| bound.clone()),
+        None => Ok(default),
+    };
+    let start = bound(start, 0)?;
+    let end = bound(end, len)?.max(start);
+    match value {
+        Value::List(items) => Ok(Value::List(Rc::from(&items[start..end]))),
+        _ => unreachable!(),
+    }
+}
+
+pub(crate) fn float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(num) => Some(*num),
+        Value::Decimal(num) => Some(num.to_f64()),
+        _ => None,
+    }
+}
+/// The items as floats for use with a matrix. Decimals are rejected rather than rounded to floats.
+fn floats(items: &[Value]) -> Option<Vec<f64>> {
+    items.iter().map(|item| match item {
+        Value::Number(num) => Some(*num),
+        _ => None,
+    }).collect()
+}
+fn vector(items: &[f64]) -> Value {
+    Value::List(items.iter().map(|&item| Value::Number(item)).collect())
+}
+/// Reads a non-empty list of equally long, non-empty lists of floats as a matrix, so lists of decimals
+/// stay lists.
+fn matrix(items: &[Value]) -> Option<Matrix> {
+    let rows = items.iter().map(|item| match item {
+        Value::List(row) => floats(row),
+        _ => None,
+    }).collect::<Option<Vec<_>>>()?;
+    Matrix::from_rows(rows)
+}
+
+/// Whether the items are equally long, non-empty lists of numbers, which are a matrix unless they hold
+/// decimals.
+fn decimal_matrix(items: &[Value]) -> bool {
+    let len = match items.first() {
+        Some(Value::List(row)) if !row.is_empty() => row.len(),
+        _ => return false,
+    };
+    items.iter().all(|item| matches!(item, Value::List(row) if row.len() == len && row.iter().all(|x| matches!(x, Value::Number(_) | Value::Decimal(_)))))
+}
+/// The error for arguments a matrix function does not take, singling out lists of decimals.
+fn not_matrices(args: Vec<Value>) -> Box<dyn Error> {
+    match args.iter().find(|arg| matches!(arg, Value::List(items) if decimal_matrix(items))) {
+        Some(arg) => Box::new(DecimalMatrix(arg.clone())),
+        None => Box::new(InvalidArguments::new(args)),
+    }
+}
+
+fn elementwise(args: &[Value], op: fn(f64, f64) -> f64) -> Option<Result<Value, Box<dyn Error>>> {
+    let matrix = match args {
+        [Value::Matrix(matrix1), Value::Matrix(matrix2)] => match matrix1.zip_with(matrix2, op) {
+            Ok(matrix) => matrix,
+            Err(err) => return Some(Err(Box::new(err))),
         },
-        [Value::Function(func), value] => {
-            let func = func.clone();
-            let value = value.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                plus(vec![func.clone()(args)?, value.clone()])
-            }))) 
+        [Value::Matrix(matrix), Value::Number(num)] => matrix.map(|x| op(x, *num)),
+        [Value::Number(num), Value::Matrix(matrix)] => matrix.map(|x| op(*num, x)),
+        _ => return None,
+    };
+    Some(Ok(Value::Matrix(Rc::new(matrix))))
+}
+
+fn broadcast(args: &[Value], op: &NativeFunction) -> Option<Result<Value, Box<dyn Error>>> {
+    let items: Result<Rc<[Value]>, _> = match args {
+        [Value::List(items1), Value::List(items2)] => {
+            if items1.len() != items2.len() {
+                return Some(Err(Box::new(InvalidArguments::new(args.to_vec()))));
+            }
+            items1.iter().zip(items2.iter()).map(|(item1, item2)| op(vec![item1.clone(), item2.clone()])).collect()
         },
+        [Value::List(items), value] => items.iter().map(|item| op(vec![item.clone(), value.clone()])).collect(),
+        [value, Value::List(items)] => items.iter().map(|item| op(vec![value.clone(), item.clone()])).collect(),
+        [Value::List(items)] => items.iter().map(|item| op(vec![item.clone()])).collect(),
+        _ => return None,
+    };
+    Some(items.map(Value::List))
+}
+
+/// Combines function operands of an operator into a lifted function.
+fn lift(operator: Operator, args: &[Value]) -> Result<Value, Box<dyn Error>> {
+    let tree = match args {
+        [operand] => Lifted::Unary(operator, Rc::new(Lifted::from_value(operand))),
+        [lhs, rhs] => Lifted::Binary(operator, Rc::new(Lifted::from_value(lhs)), Rc::new(Lifted::from_value(rhs))),
+        _ => return Err(Box::new(InvalidArguments::new(args.to_vec()))),
+    };
+    Ok(Value::Function(Rc::new(Function::lifted(tree))))
+}
+
+pub(crate) fn decimals(args: &[Value]) -> Option<(Decimal, Decimal)> {
+    match args {
+        [Value::Decimal(num1), Value::Decimal(num2)] => Some((num1.clone(), num2.clone())),
+        [Value::Decimal(num1), Value::Number(num2)] => Some((num1.clone(), Decimal::from_f64(*num2, num1.context())?)),
+        [Value::Number(num1), Value::Decimal(num2)] => Some((Decimal::from_f64(*num1, num2.context())?, num2.clone())),
+        _ => None,
+    }
+}
+
+pub(crate) fn plus(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if let Some(result) = elementwise(&args, |x, y| x + y) {
+        return result;
+    }
+    if let Some(result) = broadcast(&args, &plus) {
+        return result;
+    }
+    if let Some((num1, num2)) = decimals(&args) {
+        return match num1.checked_add(&num2) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(Overflow)),
+        };
+    }
+    match args.as_slice() {
+        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1 + num2)),
+        [Value::String(string1), Value::String(string2)] => Ok(Value::String(format!("{}{}", string1, string2).into())),
+        args if args.iter().any(|arg| matches!(arg, Value::Function(_))) => lift(Operator::Plus, args),
         [Value::Number(num)] => Ok(Value::Number(*num)),
-        [Value::Function(func)] => {
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                plus(vec![func(args)?])
-            }))) 
-        },
-        _ => Err(Box::new(InvalidArguments(args.clone()))),
+        [Value::Decimal(num)] => Ok(Value::Decimal(num.clone())),
+        _ => Err(Box::new(InvalidArguments::new(args.clone()))),
     }
 }
-fn minus(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+pub(crate) fn minus(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if let [Value::Matrix(matrix)] = args.as_slice() {
+        return Ok(Value::Matrix(Rc::new(matrix.map(|x| -x))));
+    }
+    if let Some(result) = elementwise(&args, |x, y| x - y) {
+        return result;
+    }
+    if let Some(result) = broadcast(&args, &minus) {
+        return result;
+    }
+    if let Some((num1, num2)) = decimals(&args) {
+        return match num1.checked_sub(&num2) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(Overflow)),
+        };
+    }
     match args.as_slice() {
         [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1 - num2)),
-        [Value::Function(func1), Value::Function(func2)] => {
-            let func1 = func1.clone();
-            let func2 = func2.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                minus(vec![func1(args.clone())?, func2(args)?])
-            }))) 
-        },
-        [value, Value::Function(func)] => {
-            let value = value.clone();
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                minus(vec![value.clone(), func(args)?])
-            }))) 
-        },
-        [Value::Function(func), value] => {
-            let func = func.clone();
-            let value = value.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                minus(vec![func.clone()(args)?, value.clone()])
-            }))) 
-        },
+        args if args.iter().any(|arg| matches!(arg, Value::Function(_))) => lift(Operator::Minus, args),
         [Value::Number(num)] => Ok(Value::Number(-num)),
-        [Value::Function(func)] => {
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                minus(vec![func(args)?])
-            }))) 
-        },
-        _ => Err(Box::new(InvalidArguments(args.clone()))),
+        [Value::Decimal(num)] => Ok(Value::Decimal(-num)),
+        _ => Err(Box::new(InvalidArguments::new(args.clone()))),
     }
 }
-fn star(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+pub(crate) fn star(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
     match args.as_slice() {
-        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1 * num2)),
-        [Value::Function(func1), Value::Function(func2)] => {
-            let func1 = func1.clone();
-            let func2 = func2.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                star(vec![func1(args.clone())?, func2(args)?])
-            }))) 
-        },
-        [value, Value::Function(func)] => {
-            let value = value.clone();
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                star(vec![value.clone(), func(args)?])
-            }))) 
-        },
-        [Value::Function(func), value] => {
-            let func = func.clone();
-            let value = value.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                star(vec![func.clone()(args)?, value.clone()])
-            }))) 
+        [Value::Matrix(matrix1), Value::Matrix(matrix2)] => return Ok(Value::Matrix(Rc::new(matrix1.mul(matrix2)?))),
+        [Value::Matrix(matrix), Value::List(items)] => if let Some(items) = floats(items) {
+            return Ok(vector(&matrix.mul_vector(&items)?));
         },
-        _ => Err(Box::new(InvalidArguments(args.clone()))),
+        // Multiplying lists item by item would silently give something other than the matrix product.
+        [Value::List(_), Value::List(_)] if args.iter().any(|arg| matches!(arg, Value::List(items) if decimal_matrix(items))) => return Err(not_matrices(args)),
+        _ => (),
+    }
+    if let Some(result) = elementwise(&args, |x, y| x * y) {
+        return result;
+    }
+    if let Some(result) = broadcast(&args, &star) {
+        return result;
+    }
+    if let Some((num1, num2)) = decimals(&args) {
+        return match num1.checked_mul(&num2) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(Overflow)),
+        };
+    }
+    match args.as_slice() {
+        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1 * num2)),
+        args if args.iter().any(|arg| matches!(arg, Value::Function(_))) => lift(Operator::Star, args),
+        _ => Err(Box::new(InvalidArguments::new(args.clone()))),
     }
 }
-fn slash(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+pub(crate) fn slash(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if let [Value::Matrix(_), _] = args.as_slice() {
+        if let Some(result) = elementwise(&args, |x, y| x / y) {
+            return result;
+        }
+    }
+    if let Some(result) = broadcast(&args, &slash) {
+        return result;
+    }
+    if let Some((num1, num2)) = decimals(&args) {
+        return match num1.checked_div(&num2) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(InvalidArguments::new(args))),
+        };
+    }
     match args.as_slice() {
         [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1 / num2)),
-        [Value::Function(func1), Value::Function(func2)] => {
-            let func1 = func1.clone();
-            let func2 = func2.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                slash(vec![func1(args.clone())?, func2(args)?])
-            }))) 
-        },
-        [value, Value::Function(func)] => {
-            let value = value.clone();
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                slash(vec![value.clone(), func(args)?])
-            }))) 
-        },
-        [Value::Function(func), value] => {
-            let func = func.clone();
-            let value = value.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                slash(vec![func.clone()(args)?, value.clone()])
-            }))) 
-        },
-        _ => Err(Box::new(InvalidArguments(args.clone()))),
+        args if args.iter().any(|arg| matches!(arg, Value::Function(_))) => lift(Operator::Slash, args),
+        _ => Err(Box::new(InvalidArguments::new(args.clone()))),
     }
 }
-fn percent(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+pub(crate) fn percent(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if let Some(result) = broadcast(&args, &percent) {
+        return result;
+    }
+    if let Some((num1, num2)) = decimals(&args) {
+        return match num1.checked_rem(&num2) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(InvalidArguments::new(args))),
+        };
+    }
     match args.as_slice() {
         [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1 % num2)),
-        [Value::Function(func1), Value::Function(func2)] => {
-            let func1 = func1.clone();
-            let func2 = func2.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                percent(vec![func1(args.clone())?, func2(args)?])
-            }))) 
+        args if args.iter().any(|arg| matches!(arg, Value::Function(_))) => lift(Operator::Percent, args),
+        _ => Err(Box::new(InvalidArguments::new(args.clone()))),
+    }
+}
+pub(crate) fn caret(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if let Some(result) = broadcast(&args, &caret) {
+        return result;
+    }
+    if let Some((num1, num2)) = decimals(&args) {
+        return match num1.checked_pow(&num2) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(InvalidArguments::new(args))),
+        };
+    }
+    match args.as_slice() {
+        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1.powf(*num2))),
+        args if args.iter().any(|arg| matches!(arg, Value::Function(_))) => lift(Operator::Caret, args),
+        _ => Err(Box::new(InvalidArguments::new(args.clone()))),
+    }
+}
+fn compare(args: Vec<Value>, predicate: fn(Ordering) -> bool) -> Result<Value, Box<dyn Error>> {
+    if let Some((num1, num2)) = decimals(&args) {
+        return Ok(Value::Boolean(predicate(num1.cmp(&num2))));
+    }
+    match args.as_slice() {
+        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Boolean(num1.partial_cmp(num2).is_some_and(predicate))),
+        [Value::String(string1), Value::String(string2)] => Ok(Value::Boolean(predicate(string1.cmp(string2)))),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn equal_equal(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Boolean(boolean1), Value::Boolean(boolean2)] => Ok(Value::Boolean(boolean1 == boolean2)),
+        _ => compare(args, Ordering::is_eq),
+    }
+}
+fn exclamation_equal(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match equal_equal(args)? {
+        Value::Boolean(boolean) => Ok(Value::Boolean(!boolean)),
+        value => Ok(value),
+    }
+}
+fn less(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    compare(args, Ordering::is_lt)
+}
+fn less_equal(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    compare(args, Ordering::is_le)
+}
+fn greater(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    compare(args, Ordering::is_gt)
+}
+fn greater_equal(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    compare(args, Ordering::is_ge)
+}
+fn exclamation(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Boolean(boolean)] => Ok(Value::Boolean(!boolean)),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn len(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::List(items) | Value::Tuple(items)] => Ok(Value::Number(items.len() as f64)),
+        [Value::Range(range)] => Ok(Value::Number(range.len() as f64)),
+        [Value::String(string)] => Ok(Value::Number(string.chars().count() as f64)),
+        [Value::Matrix(matrix)] => Ok(Value::Number(matrix.rows() as f64)),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn substring(string: &str, start: &Value, len: Option<&Value>) -> Option<String> {
+    let start = position(start, string.chars().count())?.max(0) as usize;
+    let len = match len {
+        Some(len) => usize::try_from(integer(len)?).ok()?,
+        None => usize::MAX,
+    };
+    Some(string.chars().skip(start).take(len).collect())
+}
+fn substr(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let substring = match args.as_slice() {
+        [Value::String(string), start] => substring(string, start, None),
+        [Value::String(string), start, len] => substring(string, start, Some(len)),
+        _ => None,
+    };
+    match substring {
+        Some(substring) => Ok(Value::String(substring.into())),
+        None => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+/// The most digits after the point of a float, those of the smallest subnormal.
+const FLOAT_FRACTION_DIGITS: usize = 1074;
+fn format(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let string = match args.as_slice() {
+        [num, digits] => integer(digits).and_then(|digits| usize::try_from(digits).ok()).filter(|&digits| digits <= MAX_FIXED_DIGITS).and_then(|digits| match num {
+            // The formatter takes at most `u16::MAX` digits, and none past those of the smallest subnormal.
+            Value::Number(num) if num.is_finite() => {
+                let shown = digits.min(FLOAT_FRACTION_DIGITS);
+                Some(format!("{:.*}{}", shown, num, "0".repeat(digits - shown)))
+            },
+            Value::Number(num) => Some(num.to_string()),
+            Value::Decimal(num) => num.to_fixed(digits),
+            _ => None,
+        }),
+        _ => None,
+    };
+    match string {
+        Some(string) => Ok(Value::String(string.into())),
+        None => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn str(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Number(num)] => Ok(Value::String(num.to_string().into())),
+        [Value::Decimal(num)] => Ok(Value::String(num.to_string().into())),
+        [Value::Boolean(boolean)] => Ok(Value::String(boolean.to_string().into())),
+        [Value::String(string)] => Ok(Value::String(string.clone())),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+/// Parses a string into a number of the evaluating scope's mode.
+fn num(args: Vec<Thunk>) -> Result<Value, Box<dyn Error>> {
+    let [arg] = args.as_slice() else {
+        return Err(Box::new(MalformedForm::default()));
+    };
+    let value = arg.eval()?;
+    let num = match (&value, arg.scope().mode()) {
+        (Value::String(string), Mode::Float) => string.trim().parse().ok().map(Value::Number),
+        (Value::String(string), Mode::Decimal(context)) => string.trim().parse::<Decimal>().ok().and_then(|num| num.with_context(context)).map(Value::Decimal),
+        _ => None,
+    };
+    match num {
+        Some(num) => Ok(num),
+        None => Err(Box::new(InvalidArguments::new(vec![value]))),
+    }
+}
+fn divmod(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if let Some((num1, num2)) = decimals(&args) {
+        let Some(mut remainder) = num1.checked_rem(&num2) else {
+            return Err(Box::new(InvalidArguments::new(args)));
+        };
+        if !remainder.is_zero() && remainder.is_negative() != num2.is_negative() {
+            let Some(sum) = remainder.checked_add(&num2) else {
+                return Err(Box::new(InvalidArguments::new(args)));
+            };
+            remainder = sum;
+        }
+        let quotient = num1.checked_sub(&remainder).and_then(|num| num.checked_div(&num2)).and_then(|num| num.quantize(0, Rounding::HalfEven));
+        let Some(quotient) = quotient else {
+            return Err(Box::new(InvalidArguments::new(args)));
+        };
+        return Ok(Value::Tuple(Rc::from([Value::Decimal(quotient), Value::Decimal(remainder)])));
+    }
+    match args.as_slice() {
+        [Value::Number(num1), Value::Number(num2)] => {
+            let mut remainder = num1 % num2;
+            if remainder != 0.0 && (remainder < 0.0) != (*num2 < 0.0) {
+                remainder += num2;
+            }
+            let quotient = ((num1 - remainder) / num2).round();
+            Ok(Value::Tuple(Rc::from([Value::Number(quotient), Value::Number(remainder)])))
         },
-        [value, Value::Function(func)] => {
-            let value = value.clone();
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                percent(vec![value.clone(), func(args)?])
-            }))) 
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn polar(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if let Some((x, y)) = decimals(&args) {
+        let (Some(radius), Some(angle)) = (x.hypot(&y), y.atan2(&x)) else {
+            return Err(Box::new(InvalidArguments::new(args)));
+        };
+        return Ok(Value::Tuple(Rc::from([Value::Decimal(radius), Value::Decimal(angle)])));
+    }
+    match args.as_slice() {
+        [Value::Number(x), Value::Number(y)] => Ok(Value::Tuple(Rc::from([Value::Number(x.hypot(*y)), Value::Number(y.atan2(*x))]))),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+/// The items of a list, tuple or range in order, computing those of a range as they are reached. The
+/// items of a matrix are its rows as lists.
+fn items(value: &Value) -> Option<Box<dyn Iterator<Item = Value> + '_>> {
+    match value {
+        Value::List(items) | Value::Tuple(items) => Some(Box::new(items.iter().cloned())),
+        Value::Range(range) => Some(Box::new(range.iter())),
+        Value::Matrix(matrix) => Some(Box::new((0..matrix.rows()).map(|row| vector(matrix.row(row))))),
+        _ => None,
+    }
+}
+fn list(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let ([value], Some(items)) = (args.as_slice(), args.first().and_then(items)) else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    match value {
+        Value::List(_) => Ok(value.clone()),
+        _ => Ok(Value::List(items.collect())),
+    }
+}
+fn map(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let ([Value::Function(func), value], Some(items)) = (args.as_slice(), args.get(1).and_then(items)) else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    let mapped = items.map(|item| func.call(vec![item])).collect::<Result<_, _>>()?;
+    match value {
+        Value::Tuple(_) => Ok(Value::Tuple(mapped)),
+        _ => Ok(Value::List(mapped)),
+    }
+}
+fn filter(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let ([Value::Function(func), value], Some(items)) = (args.as_slice(), args.get(1).and_then(items)) else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    let mut kept = Vec::new();
+    for item in items {
+        match func.call(vec![item.clone()])? {
+            Value::Boolean(true) => kept.push(item),
+            Value::Boolean(false) => (),
+            value => return Err(Box::new(ExpressionError::ExpectedBoolean(value))),
+        }
+    }
+    match value {
+        Value::Tuple(_) => Ok(Value::Tuple(kept.into())),
+        _ => Ok(Value::List(kept.into())),
+    }
+}
+fn fold(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let ([Value::Function(func), init, _], Some(mut items)) = (args.as_slice(), args.get(2).and_then(items)) else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    items.try_fold(init.clone(), |acc, item| func.call(vec![acc, item]))
+}
+/// Lists `x0` and the results of applying `f` to it `n` times in a row.
+fn iterate(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let count = match args.as_slice() {
+        [_, _, n] => integer(n).and_then(|n| usize::try_from(n).ok()),
+        _ => None,
+    };
+    let ([Value::Function(func), x0, _], Some(count)) = (args.as_slice(), count) else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    let mut items = vec![x0.clone()];
+    for _ in 0..count {
+        let item = func.call(vec![items[items.len() - 1].clone()])?;
+        items.push(item);
+    }
+    Ok(Value::List(items.into()))
+}
+/// Whether two numbers, or equally long lists of them, differ by at most `tol` everywhere.
+fn within(value1: &Value, value2: &Value, tol: &Value) -> Option<bool> {
+    match (value1, value2) {
+        (Value::List(items1), Value::List(items2)) | (Value::Tuple(items1), Value::Tuple(items2)) if items1.len() == items2.len() => {
+            items1.iter().zip(items2.iter()).try_fold(true, |all, (item1, item2)| Some(within(item1, item2, tol)? && all))
         },
-        [Value::Function(func), value] => {
-            let func = func.clone();
-            let value = value.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                percent(vec![func.clone()(args)?, value.clone()])
-            }))) 
+        _ => match decimals(&[value1.clone(), value2.clone()]) {
+            Some((num1, num2)) => {
+                let tol = match tol {
+                    Value::Decimal(tol) => tol.clone(),
+                    tol => Decimal::from_f64(float(tol)?, num1.context())?,
+                };
+                Some(num1.checked_sub(&num2)?.abs() <= tol)
+            },
+            None => Some((float(value1)? - float(value2)?).abs() <= float(tol)?),
         },
-        _ => Err(Box::new(InvalidArguments(args.clone()))),
     }
 }
-fn caret(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+/// Applies `f` from `x0` on until the result changes by at most `tol`, giving up after `limit` steps.
+fn fixpoint(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let limit = match args.as_slice() {
+        [_, _, _] => Some(1000),
+        [_, _, _, limit] => integer(limit).and_then(|limit| usize::try_from(limit).ok()),
+        _ => None,
+    };
+    let ([Value::Function(func), x0, tol, ..], Some(limit)) = (args.as_slice(), limit) else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    let mut x = x0.clone();
+    for _ in 0..limit {
+        let next = func.call(vec![x.clone()])?;
+        match within(&next, &x, tol) {
+            Some(true) => return Ok(next),
+            Some(false) => x = next,
+            None => return Err(Box::new(InvalidArguments::new(vec![next, x, tol.clone()]))),
+        }
+    }
+    Err(Box::new(NoConvergence { iterations: limit, last: x }))
+}
+/// Lists `f(x)` for `x` from `a` to `b` inclusive in steps of `step`.
+fn tabulate(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let (func, a, b, step) = match args.as_slice() {
+        [Value::Function(func), a, b] => (func, a, b, Value::Number(1.0)),
+        [Value::Function(func), a, b, step] => (func, a, b, step.clone()),
+        _ => return Err(Box::new(InvalidArguments::new(args))),
+    };
+    let Some(count) = steps(a, b, &step) else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    let mut items = Vec::new();
+    for i in 0..count {
+        let x = plus(vec![a.clone(), star(vec![Value::Number(i as f64), step.clone()])?])?;
+        items.push(func.call(vec![x])?);
+    }
+    Ok(Value::List(items.into()))
+}
+/// Combines `op` over the values of the body with the index bound to each item of the range, starting from `init`.
+fn big_operator(args: Vec<Thunk>, init: i64, op: &NativeFunction) -> Result<Value, Box<dyn Error>> {
+    let [binding, body] = args.as_slice() else {
+        return Err(Box::new(MalformedForm::default()));
+    };
+    let ParseTree::Binding(name, domain) = binding.tree() else {
+        return Err(Box::new(MalformedForm::default()));
+    };
+    let domain = binding.scope().eval(domain)?;
+    let Some(items) = items(&domain) else {
+        return Err(Box::new(InvalidArguments::new(vec![domain])));
+    };
+    let mut acc = match binding.scope().mode() {
+        Mode::Float => Value::Number(init as f64),
+        Mode::Decimal(context) => Value::Decimal(Decimal::from_i64(init, context)),
+    };
+    for item in items {
+        acc = op(vec![acc, body.eval_with(name, item)?])?;
+    }
+    Ok(acc)
+}
+fn sum(args: Vec<Thunk>) -> Result<Value, Box<dyn Error>> {
+    big_operator(args, 0, &plus)
+}
+fn prod(args: Vec<Thunk>) -> Result<Value, Box<dyn Error>> {
+    big_operator(args, 1, &star)
+}
+fn ring(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
     match args.as_slice() {
-        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(num1.powf(*num2))),
-        [Value::Function(func1), Value::Function(func2)] => {
-            let func1 = func1.clone();
-            let func2 = func2.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                caret(vec![func1(args.clone())?, func2(args)?])
-            }))) 
+        [outer @ Value::Function(_), inner @ Value::Function(_)] => {
+            Ok(Value::Function(Rc::new(Function::lifted(Lifted::Compose(Rc::new(Lifted::from_value(outer)), Rc::new(Lifted::from_value(inner)))))))
         },
-        [value, Value::Function(func)] => {
-            let value = value.clone();
-            let func = func.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                caret(vec![value.clone(), func(args)?])
-            }))) 
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn simplify(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [value @ Value::Function(_)] => Ok(Lifted::from_value(value).simplify().into_value()),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn derivative(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [value @ Value::Function(_)] => match Lifted::from_value(value).derivative() {
+            Some(tree) => Ok(tree.simplify().into_value()),
+            None => Err(Box::new(InvalidArguments::new(args))),
         },
-        [Value::Function(func), value] => {
-            let func = func.clone();
-            let value = value.clone();
-            Ok(Value::Function(Rc::new(move |args| {
-                caret(vec![func.clone()(args)?, value.clone()])
-            }))) 
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn partial(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Function(func)] if func.arity.is_some() => Ok(Value::Function(Rc::new(func.apply(Vec::new())))),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn transpose(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Matrix(matrix)] => Ok(Value::Matrix(Rc::new(matrix.transpose()))),
+        _ => Err(not_matrices(args)),
+    }
+}
+fn det(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Matrix(matrix)] => Ok(Value::Number(matrix.det()?)),
+        _ => Err(not_matrices(args)),
+    }
+}
+fn inverse(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Matrix(matrix)] => Ok(Value::Matrix(Rc::new(matrix.inverse()?))),
+        _ => Err(not_matrices(args)),
+    }
+}
+fn solve(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Matrix(matrix), Value::Matrix(rhs)] => Ok(Value::Matrix(Rc::new(matrix.solve(rhs)?))),
+        [Value::Matrix(matrix), Value::List(items)] => match floats(items) {
+            Some(items) => Ok(vector(&matrix.solve_vector(&items)?)),
+            None => Err(Box::new(InvalidArguments::new(args))),
         },
-        _ => Err(Box::new(InvalidArguments(args.clone()))),
+        _ => Err(not_matrices(args)),
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Float,
+    Decimal(Context),
+}
+
+/// A chain of variable bindings ending in the table an expression is evaluated with.
 #[derive(Clone)]
-pub struct Expression {
-    tree: ParseTree,
-    pub table: HashMap<String, Value>,
+pub struct Scope {
+    bindings: HashMap<String, Value>,
+    parent: Option<Rc<Scope>>,
+    mode: Mode,
+    /// The files being imported, outermost first, against the last of which imports are resolved.
+    imports: Rc<[PathBuf]>,
 }
-impl Expression {
-    fn eval_tree(&self, tree: &ParseTree) -> Result<Value, ExpressionError> {
+impl Scope {
+    pub fn new(table: HashMap<String, Value>, mode: Mode) -> Self {
+        Self {
+            bindings: table,
+            parent: None,
+            mode,
+            imports: Rc::from([]),
+        }
+    }
+    pub fn bind(self: &Rc<Self>, bindings: HashMap<String, Value>) -> Rc<Self> {
+        Rc::new(Self {
+            bindings,
+            parent: Some(self.clone()),
+            mode: self.mode,
+            imports: self.imports.clone(),
+        })
+    }
+    /// Adds the bindings in place when nothing else refers to the scope, and otherwise in a child
+    /// scope, so that functions keep seeing the bindings they were defined with.
+    fn extend(mut self: Rc<Self>, bindings: HashMap<String, Value>) -> Rc<Self> {
+        match Rc::get_mut(&mut self) {
+            Some(scope) => {
+                scope.bindings.extend(bindings);
+                self
+            },
+            None => self.bind(bindings),
+        }
+    }
+    /// Reads, parses and evaluates a file as a module, relative to the file being imported if any.
+    fn import(self: &Rc<Self>, path: &str) -> Result<Record, Box<dyn Error>> {
+        let path = match self.imports.last().and_then(|file| file.parent()) {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        }.canonicalize()?;
+        if let Some(start) = self.imports.iter().position(|file| *file == path) {
+            let cycle = self.imports[start..].iter().chain([&path]).map(|file| file.display().to_string()).collect::<Vec<_>>();
+            return Err(format!("import cycle {}", cycle.join(" -> ")).into());
+        }
+        let source = fs::read_to_string(&path)?;
+        let program = Program::try_from(source.as_str()).map_err(|err| err.to_string())?;
+        let scope = Rc::new(Self {
+            bindings: HashMap::new(),
+            parent: Some(self.clone()),
+            mode: self.mode,
+            imports: self.imports.iter().cloned().chain([path]).collect(),
+        });
+        Ok(program.eval_module(&scope)?)
+    }
+    pub fn get(&self, ident: &str) -> Option<&Value> {
+        match self.bindings.get(ident) {
+            Some(value) => Some(value),
+            None => self.parent.as_ref().and_then(|parent| parent.get(ident)),
+        }
+    }
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    /// Flattens the chain of bindings into a single table.
+    pub fn table(&self) -> HashMap<String, Value> {
+        let mut table = self.parent.as_ref().map(|parent| parent.table()).unwrap_or_default();
+        table.extend(self.bindings.iter().map(|(name, value)| (name.clone(), value.clone())));
+        table
+    }
+    fn call(self: &Rc<Self>, func: &ParseTree, args: &[ParseTree]) -> Result<Value, ExpressionError> {
+        match self.eval(func)? {
+            Value::Function(func) => {
+                let args = args.iter().map(|args| self.eval(args)).collect::<Result<Vec<_>,_>>()?;
+                func.call(args).map_err(ExpressionError::call_failure)
+            },
+            Value::Form(form) => {
+                form.call(args.iter().map(|tree| Thunk { tree, scope: self }).collect()).map_err(ExpressionError::call_failure)
+            },
+            func => Err(ExpressionError::FunctionCallFailure(Box::new(InvalidFunction(func)))),
+        }
+    }
+    pub fn eval(self: &Rc<Self>, tree: &ParseTree) -> Result<Value, ExpressionError> {
         match tree {
-            ParseTree::Number(num) => Ok(Value::Number(*num)),
-            ParseTree::Identifier(ident) => self.table.get(ident).map_or(Err(ExpressionError::UndefinedIdentifier(ident.clone())), |args| Ok(args.clone())),
-            ParseTree::FunctionCall(func, args) => {
-                let func = self.eval_tree(func)?;
-                let args = args.iter().map(|args| self.eval_tree(args)).collect::<Result<Vec<_>,_>>()?;
-                if let Value::Function(func) = func {
-                    func(args).map_err(|err| ExpressionError::FunctionCallFailure(err))
-                } else {
-                    Err(ExpressionError::FunctionCallFailure(Box::new(InvalidFunction(func))))
-                }
+            ParseTree::Number(num, float) => match self.mode {
+                Mode::Float => Ok(Value::Number(*float)),
+                Mode::Decimal(context) => match num.with_context(context) {
+                    Some(num) => Ok(Value::Decimal(num)),
+                    None => Err(ExpressionError::FunctionCallFailure(Box::new(Overflow))),
+                },
+            },
+            ParseTree::String(string) => Ok(Value::String(string.as_str().into())),
+            ParseTree::Identifier(ident) => self.get(ident).map_or(Err(ExpressionError::UndefinedIdentifier(ident.clone())), |args| Ok(args.clone())),
+            ParseTree::FunctionCall(func, args) => self.call(func, args),
+            ParseTree::Pipe(arg, func) => self.call(func, std::slice::from_ref(arg)),
+            ParseTree::Range(start, end, step) => self.range(start, end, step.as_deref()),
+            ParseTree::Binding(name, _) => Err(ExpressionError::UnexpectedBinding(name.clone())),
+            ParseTree::And(lhs, rhs) => match self.eval(lhs)? {
+                Value::Boolean(false) => Ok(Value::Boolean(false)),
+                Value::Boolean(true) => match self.eval(rhs)? {
+                    Value::Boolean(boolean) => Ok(Value::Boolean(boolean)),
+                    value => Err(ExpressionError::ExpectedBoolean(value)),
+                },
+                value => Err(ExpressionError::ExpectedBoolean(value)),
+            },
+            ParseTree::Or(lhs, rhs) => match self.eval(lhs)? {
+                Value::Boolean(true) => Ok(Value::Boolean(true)),
+                Value::Boolean(false) => match self.eval(rhs)? {
+                    Value::Boolean(boolean) => Ok(Value::Boolean(boolean)),
+                    value => Err(ExpressionError::ExpectedBoolean(value)),
+                },
+                value => Err(ExpressionError::ExpectedBoolean(value)),
+            },
+            ParseTree::Conditional(condition, consequent, alternative) => match self.eval(condition)? {
+                Value::Boolean(true) => self.eval(consequent),
+                Value::Boolean(false) => self.eval(alternative),
+                value => Err(ExpressionError::ExpectedBoolean(value)),
+            },
+            ParseTree::Piecewise(branches, otherwise, span) => self.piecewise(branches, otherwise.as_deref(), *span),
+            ParseTree::Lambda(params, body) => Ok(self.lambda(None, params, body)),
+            ParseTree::Definition(name, params, body) => Ok(self.lambda(Some(name), params, body)),
+            ParseTree::Clauses(name, clauses) => Ok(Lambda::new(Some(name.clone()), clauses.clone(), self).value()),
+            ParseTree::Recursive(members) => Ok(self.group(members)),
+            ParseTree::Assignment(_, value) => self.eval(value),
+            ParseTree::Destructuring(names, value) => match self.eval(value)? {
+                Value::Tuple(items) if items.len() == names.len() => Ok(Value::Tuple(items)),
+                value => Err(ExpressionError::InvalidDestructuring(names.len(), value)),
+            },
+            ParseTree::Use(module) => match self.eval(module)? {
+                Value::Record(record) => Ok(Value::Record(record)),
+                value => Err(ExpressionError::ExpectedRecord(value)),
+            },
+            ParseTree::Import(path) => match self.import(path) {
+                Ok(module) => Ok(Value::record(module)),
+                Err(err) => Err(ExpressionError::ImportFailure(path.clone(), err)),
+            },
+            ParseTree::Let(bindings, body) => self.let_in(bindings, body),
+            ParseTree::List(items) => self.list(items),
+            ParseTree::Tuple(items) => Ok(Value::Tuple(items.iter().map(|item| self.eval(item)).collect::<Result<_, _>>()?)),
+            ParseTree::Record(fields) => self.record(fields),
+            ParseTree::Field(value, name) => {
+                let value = self.eval(value)?;
+                field(&value, name).ok_or(ExpressionError::UndefinedField(value, name.clone()))
+            },
+            ParseTree::Index(value, position) => self.index(value, position),
+            ParseTree::Slice(value, start, end) => self.slice(value, start.as_deref(), end.as_deref()),
+            ParseTree::LeftSection(operand, operator) => self.section(tree, operator, operand, true),
+            ParseTree::RightSection(operator, operand) => self.section(tree, operator, operand, false),
+        }
+    }
+    // The less common kinds of tree are evaluated apart from `eval`, whose frame then stays small
+    // enough for deep recursion through it.
+    fn range(self: &Rc<Self>, start: &ParseTree, end: &ParseTree, step: Option<&ParseTree>) -> Result<Value, ExpressionError> {
+        let (start, end) = (self.eval(start)?, self.eval(end)?);
+        let step = match step {
+            Some(step) => self.eval(step)?,
+            None => Value::Number(1.0),
+        };
+        match Range::new(start.clone(), end.clone(), step.clone()) {
+            Some(range) => Ok(Value::Range(Rc::new(range))),
+            None => Err(ExpressionError::InvalidRange(vec![start, end, step])),
+        }
+    }
+    fn piecewise(self: &Rc<Self>, branches: &[(ParseTree, ParseTree)], otherwise: Option<&ParseTree>, span: Span) -> Result<Value, ExpressionError> {
+        for (condition, value) in branches {
+            match self.eval(condition)? {
+                Value::Boolean(true) => return self.eval(value),
+                Value::Boolean(false) => (),
+                value => return Err(ExpressionError::ExpectedBoolean(value)),
+            }
+        }
+        match otherwise {
+            Some(otherwise) => self.eval(otherwise),
+            None => Err(ExpressionError::NoMatchingBranch(span)),
+        }
+    }
+    fn lambda(self: &Rc<Self>, name: Option<&String>, params: &[String], body: &ParseTree) -> Value {
+        let patterns = params.iter().cloned().map(Pattern::Name).collect();
+        Lambda::new(name.cloned(), vec![(patterns, body.clone())], self).value()
+    }
+    /// The functions of a `rec` group as a tuple, in order.
+    fn group(self: &Rc<Self>, members: &[ParseTree]) -> Value {
+        let members = members.iter().filter_map(|member| match member {
+            ParseTree::Definition(name, params, body) => {
+                let patterns = params.iter().cloned().map(Pattern::Name).collect();
+                Some(Lambda::new(Some(name.clone()), vec![(patterns, (**body).clone())], self))
             },
+            ParseTree::Clauses(name, clauses) => Some(Lambda::new(Some(name.clone()), clauses.clone(), self)),
+            _ => None,
+        }).collect();
+        Value::Tuple(Lambda::group(members).into())
+    }
+    fn let_in(self: &Rc<Self>, bindings: &[(String, ParseTree)], body: &ParseTree) -> Result<Value, ExpressionError> {
+        let mut scope = self.clone();
+        for (name, value) in bindings {
+            let value = scope.eval(value).map_err(|err| ExpressionError::BindingFailure(name.clone(), Box::new(err)))?;
+            scope = scope.bind(HashMap::from([(name.clone(), value)]));
+        }
+        scope.eval(body)
+    }
+    fn list(self: &Rc<Self>, items: &[ParseTree]) -> Result<Value, ExpressionError> {
+        let items = items.iter().map(|item| self.eval(item)).collect::<Result<Vec<_>, _>>()?;
+        match matrix(&items) {
+            Some(matrix) => Ok(Value::Matrix(Rc::new(matrix))),
+            None => Ok(Value::List(items.into())),
+        }
+    }
+    fn record(self: &Rc<Self>, fields: &[(String, ParseTree)]) -> Result<Value, ExpressionError> {
+        let mut record = Record::new();
+        for (name, value) in fields {
+            record.insert(name.clone(), self.eval(value)?);
+        }
+        Ok(Value::record(record))
+    }
+    fn index(self: &Rc<Self>, value: &ParseTree, position: &ParseTree) -> Result<Value, ExpressionError> {
+        let value = self.eval(value)?;
+        let position = self.eval(position)?;
+        index(&value, &position).ok_or(ExpressionError::InvalidIndex(value, position))
+    }
+    fn slice(self: &Rc<Self>, value: &ParseTree, start: Option<&ParseTree>, end: Option<&ParseTree>) -> Result<Value, ExpressionError> {
+        let value = self.eval(value)?;
+        let start = start.map(|start| self.eval(start)).transpose()?;
+        let end = end.map(|end| self.eval(end)).transpose()?;
+        slice(&value, start.as_ref(), end.as_ref()).map_err(|position| ExpressionError::InvalidIndex(value, position))
+    }
+    fn section(self: &Rc<Self>, tree: &ParseTree, operator: &str, operand: &ParseTree, left: bool) -> Result<Value, ExpressionError> {
+        let func = match self.get(operator) {
+            Some(Value::Function(func)) => func.clone(),
+            Some(value) => return Err(ExpressionError::FunctionCallFailure(Box::new(InvalidFunction(value.clone())))),
+            None => return Err(ExpressionError::UndefinedIdentifier(operator.to_owned())),
+        };
+        let operand = self.eval(operand)?;
+        Ok(Value::Function(Rc::new(Function {
+            name: Some(tree.to_string()),
+            arity: Some(1..=1),
+            pure: func.pure,
+            ..Function::new(move |args| {
+                let mut args = args.into_iter();
+                let arg = args.next().expect("a section takes one argument");
+                func.call(if left { vec![operand.clone(), arg] } else { vec![arg, operand.clone()] })
+            })
+        })))
+    }
+}
+
+/// The most calls of user functions that may be in progress at once.
+const MAX_DEPTH: usize = 1000;
+/// The stack that calls of user functions may use unless [`set_stack_limit`] says otherwise, which
+/// leaves room for the caller within the 2 MiB stack of a thread spawned by `std::thread`.
+const DEFAULT_STACK_LIMIT: usize = 1 << 20;
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// The stack address at the outermost call in progress, from which the stack used is measured.
+    static STACK_BASE: Cell<usize> = const { Cell::new(0) };
+    static STACK_LIMIT: Cell<usize> = const { Cell::new(DEFAULT_STACK_LIMIT) };
+}
+/// Sets how many bytes of stack nested calls of user functions may use on the current thread before
+/// evaluation fails with a recursion limit error rather than overflowing the stack. Raise it when
+/// evaluating on a thread with a bigger stack than the default.
+pub fn set_stack_limit(bytes: usize) {
+    STACK_LIMIT.set(bytes);
+}
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+/// A call of a user function in progress, counted until it is dropped.
+struct Depth;
+impl Depth {
+    fn enter(name: &Option<String>) -> Result<Self, ExpressionError> {
+        let address = stack_address();
+        if DEPTH.get() == 0 {
+            STACK_BASE.set(address);
+        }
+        if DEPTH.get() >= MAX_DEPTH || STACK_BASE.get().abs_diff(address) > STACK_LIMIT.get() {
+            return Err(ExpressionError::RecursionLimit(name.clone().unwrap_or_else(|| "<anonymous>".to_owned())));
+        }
+        DEPTH.set(DEPTH.get() + 1);
+        Ok(Self)
+    }
+}
+impl Drop for Depth {
+    fn drop(&mut self) {
+        DEPTH.set(DEPTH.get() - 1);
+    }
+}
+
+/// A user function, which sees itself and the other functions of its group under their names so that
+/// definitions can recurse.
+struct Lambda {
+    name: Option<String>,
+    params: Vec<String>,
+    clauses: Vec<(Vec<Pattern>, ParseTree)>,
+    scope: Rc<Scope>,
+    pure: bool,
+    /// Results of earlier calls of a pure named function, by their arguments.
+    cache: Option<RefCell<HashMap<Vec<Key>, Value>>>,
+}
+/// An argument of a memoised call, compared by value.
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Number(u64),
+    Decimal(Decimal),
+    Boolean(bool),
+    String(Rc<str>),
+}
+impl Key {
+    fn new(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(num) => Some(Self::Number(num.to_bits())),
+            Value::Decimal(num) => Some(Self::Decimal(num.clone())),
+            Value::Boolean(boolean) => Some(Self::Boolean(*boolean)),
+            Value::String(string) => Some(Self::String(string.clone())),
+            _ => None,
+        }
+    }
+}
+impl Lambda {
+    /// A function trying its clauses in order. It is pure unless its bodies refer to an impure function,
+    /// and is memoised when it is also named.
+    fn new(name: Option<String>, clauses: Vec<(Vec<Pattern>, ParseTree)>, scope: &Rc<Scope>) -> Self {
+        let arity = clauses.first().map_or(0, |(patterns, _)| patterns.len());
+        let params = (0..arity).map(|i| {
+            clauses.iter().find_map(|(patterns, _)| match &patterns[i] {
+                Pattern::Name(name) => Some(name.clone()),
+                Pattern::Literal(_) => None,
+            }).unwrap_or_else(|| "_".to_owned())
+        }).collect();
+        let pure = clauses.iter().all(|(_, body)| body.identifiers().into_iter().all(|ident| {
+            !matches!(scope.get(ident), Some(Value::Function(func)) if !func.pure)
+        }));
+        Self {
+            cache: (pure && name.is_some()).then(RefCell::default),
+            name,
+            params,
+            clauses,
+            scope: scope.clone(),
+            pure,
+        }
+    }
+    /// The values of functions defined together. The group shares its functions rather than holding
+    /// their values, which would make a cycle, and is pure only if every one of them is.
+    fn group(mut members: Vec<Self>) -> Vec<Value> {
+        if !members.iter().all(|member| member.pure) {
+            for member in &mut members {
+                member.pure = false;
+                member.cache = None;
+            }
+        }
+        let group: Rc<[Self]> = members.into();
+        (0..group.len()).map(|index| Self::member(&group, index)).collect()
+    }
+    /// The names bound by matching the arguments against a clause's patterns, if they match.
+    fn bind(&self, patterns: &[Pattern], args: &[Value]) -> Result<Option<HashMap<String, Value>>, ExpressionError> {
+        let mut bindings = HashMap::new();
+        for (pattern, arg) in patterns.iter().zip(args) {
+            match pattern {
+                Pattern::Name(name) => {
+                    bindings.insert(name.clone(), arg.clone());
+                },
+                Pattern::Literal(literal) => {
+                    let literal = self.scope.eval(literal)?;
+                    if !matches!(equal_equal(vec![arg.clone(), literal]), Ok(Value::Boolean(true))) {
+                        return Ok(None);
+                    }
+                },
+            }
+        }
+        Ok(Some(bindings))
+    }
+    fn call(group: &Rc<[Self]>, index: usize, args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+        let this = &group[index];
+        let key = match &this.cache {
+            Some(_) => args.iter().map(Key::new).collect::<Option<Vec<_>>>(),
+            None => None,
+        };
+        if let (Some(cache), Some(key)) = (&this.cache, &key) {
+            if let Some(value) = cache.borrow().get(key) {
+                return Ok(value.clone());
+            }
+        }
+        let _depth = Depth::enter(&this.name)?;
+        for (patterns, body) in &this.clauses {
+            let Some(mut bindings) = this.bind(patterns, &args)? else {
+                continue;
+            };
+            for (index, member) in group.iter().enumerate() {
+                if let Some(name) = &member.name {
+                    bindings.entry(name.clone()).or_insert_with(|| Self::member(group, index));
+                }
+            }
+            let value = this.scope.bind(bindings).eval(body)?;
+            if let (Some(cache), Some(key)) = (&this.cache, key) {
+                cache.borrow_mut().insert(key, value.clone());
+            }
+            return Ok(value);
+        }
+        Err(Box::new(ExpressionError::NoMatchingClause(this.name.clone().unwrap_or_default(), args)))
+    }
+    fn value(self) -> Value {
+        Self::member(&Rc::from([self]), 0)
+    }
+    fn member(group: &Rc<[Self]>, index: usize) -> Value {
+        let this = &group[index];
+        let group = group.clone();
+        Value::Function(Rc::new(Function {
+            name: this.name.clone(),
+            arity: Some(this.params.len()..=this.params.len()),
+            params: Some(this.params.clone()),
+            doc: None,
+            pure: this.pure,
+            derivative: None,
+            partial: false,
+            body: Body::Native(Box::new(move |args| Self::call(&group, index, args))),
+        }))
+    }
+}
+
+/// An unevaluated argument passed to a [`Value::Form`].
+#[derive(Clone, Copy)]
+pub struct Thunk<'a> {
+    tree: &'a ParseTree,
+    scope: &'a Rc<Scope>,
+}
+impl<'a> Thunk<'a> {
+    pub fn tree(&self) -> &'a ParseTree {
+        self.tree
+    }
+    pub fn scope(&self) -> &'a Rc<Scope> {
+        self.scope
+    }
+    pub fn eval(&self) -> Result<Value, ExpressionError> {
+        self.scope.eval(self.tree)
+    }
+    pub fn eval_with(&self, ident: &str, value: Value) -> Result<Value, ExpressionError> {
+        self.scope.bind(HashMap::from([(ident.to_owned(), value)])).eval(self.tree)
+    }
+}
+
+/// The names a statement installs into the table, given the value it evaluated to.
+fn bindings(statement: &ParseTree, value: &Value) -> Vec<(String, Value)> {
+    match (statement, value) {
+        (ParseTree::Definition(name, _, _) | ParseTree::Clauses(name, _) | ParseTree::Assignment(name, _), value) => vec![(name.clone(), value.clone())],
+        (ParseTree::Recursive(members), Value::Tuple(items)) => members.iter().zip(items.iter()).filter_map(|(member, value)| match member {
+            ParseTree::Definition(name, _, _) | ParseTree::Clauses(name, _) => Some((name.clone(), value.clone())),
+            _ => None,
+        }).collect(),
+        (ParseTree::Destructuring(names, _), Value::Tuple(items)) => names.iter().cloned().zip(items.iter().cloned()).collect(),
+        (ParseTree::Use(_), Value::Record(record)) => record.iter().map(|(name, value)| (name.to_owned(), value.clone())).collect(),
+        (ParseTree::Import(path), value) => {
+            let name = Path::new(path).file_stem().and_then(|stem| stem.to_str()).unwrap_or(path);
+            vec![(name.to_owned(), value.clone())]
+        },
+        _ => Vec::new(),
+    }
+}
+
+impl Program {
+    /// Evaluates the statements in order, each seeing what the earlier ones define, and returns the
+    /// value of the last one together with everything defined.
+    pub fn eval(&self, table: &HashMap<String, Value>, mode: Mode) -> Result<(Value, HashMap<String, Value>), ExpressionError> {
+        let mut defined = HashMap::new();
+        let value = self.eval_in(Rc::new(Scope::new(table.clone(), mode)), |_, name, value| {
+            defined.insert(name, value);
+        })?;
+        Ok((value, defined))
+    }
+    /// Evaluates the statements like [`Program::eval`] in the given scope and collects what they
+    /// define, apart from the names brought in by `use`, into a module.
+    pub fn eval_module(&self, scope: &Rc<Scope>) -> Result<Record, ExpressionError> {
+        let mut module = Record::new();
+        self.eval_in(scope.clone(), |statement, name, value| {
+            if !matches!(statement, ParseTree::Use(_)) {
+                module.insert(name, value);
+            }
+        })?;
+        Ok(module)
+    }
+    /// Evaluates the statements in one scope that grows with what they define, passing each name
+    /// and value defined to `define`.
+    fn eval_in(&self, mut scope: Rc<Scope>, mut define: impl FnMut(&ParseTree, String, Value)) -> Result<Value, ExpressionError> {
+        let mut value = None;
+        for statement in &self.statements {
+            let result = scope.eval(statement)?;
+            let bindings = bindings(statement, &result);
+            if !bindings.is_empty() {
+                for (name, value) in &bindings {
+                    define(statement, name.clone(), value.clone());
+                }
+                scope = scope.extend(bindings.into_iter().collect());
+            }
+            value = Some(result);
         }
+        Ok(value.expect("a program has at least one statement"))
     }
+}
+
+#[derive(Clone)]
+pub struct Expression {
+    program: Program,
+    pub table: HashMap<String, Value>,
+    pub mode: Mode,
+}
+impl Expression {
     pub fn eval(&self) -> Result<Value, ExpressionError> {
-        self.eval_tree(&self.tree)
+        self.eval_bindings().map(|(value, _)| value)
+    }
+    /// Evaluates the expression like [`Expression::eval`], also returning the names its statements define.
+    pub fn eval_bindings(&self) -> Result<(Value, HashMap<String, Value>), ExpressionError> {
+        self.program.eval(&self.table, self.mode)
     }
 }
 impl<'a> TryFrom<&'a str> for Expression {
     type Error = ParseError<'a>;
 
     fn try_from(string: &'a str) -> Result<Self, Self::Error> {
+        let program = Parser::from(string).parse_program()?;
+        let mut table = HashMap::from([
+            ("+".to_owned(), Value::native("+", &["x", "y?"], "Adds numbers, decimals, matrices, lists item by item or functions pointwise, joins strings, or returns x unchanged.", plus)),
+            ("-".to_owned(), Value::native("-", &["x", "y?"], "Subtracts y from x, or negates x.", minus)),
+            ("*".to_owned(), Value::native("*", &["x", "y"], "Multiplies x by y, as matrices when both are matrices.", star)),
+            ("/".to_owned(), Value::native("/", &["x", "y"], "Divides x by y.", slash)),
+            ("%".to_owned(), Value::native("%", &["x", "y"], "The remainder of dividing x by y.", percent)),
+            ("^".to_owned(), Value::native("^", &["x", "y"], "Raises x to the power y.", caret)),
+            ("==".to_owned(), Value::native("==", &["x", "y"], "Whether x equals y.", equal_equal)),
+            ("!=".to_owned(), Value::native("!=", &["x", "y"], "Whether x differs from y.", exclamation_equal)),
+            ("<".to_owned(), Value::native("<", &["x", "y"], "Whether x is less than y.", less)),
+            ("<=".to_owned(), Value::native("<=", &["x", "y"], "Whether x is at most y.", less_equal)),
+            (">".to_owned(), Value::native(">", &["x", "y"], "Whether x is greater than y.", greater)),
+            (">=".to_owned(), Value::native(">=", &["x", "y"], "Whether x is at least y.", greater_equal)),
+            ("∘".to_owned(), Value::native("∘", &["f", "g"], "The composition of f after g, i.e. x -> f(g(x)).", ring)),
+            ("!".to_owned(), Value::native("!", &["x"], "The negation of a boolean.", exclamation)),
+            ("len".to_owned(), Value::native("len", &["x"], "The number of items in a list, tuple or range, characters in a string or rows in a matrix.", len)),
+            ("transpose".to_owned(), Value::native("transpose", &["matrix"], "The transpose of a matrix.", transpose)),
+            ("det".to_owned(), Value::native("det", &["matrix"], "The determinant of a square matrix.", det)),
+            ("inverse".to_owned(), Value::native("inverse", &["matrix"], "The inverse of a square matrix.", inverse)),
+            ("solve".to_owned(), Value::native("solve", &["matrix", "rhs"], "Solves matrix * x = rhs for x, where rhs is a matrix or a list.", solve)),
+            ("substr".to_owned(), Value::native("substr", &["string", "start", "len?"], "The characters of a string from start on, or only len of them.", substr)),
+            ("format".to_owned(), Value::native("format", &["x", "digits"], "Formats a number with the given number of digits after the point.", format)),
+            ("str".to_owned(), Value::native("str", &["x"], "Converts a number, decimal or boolean to a string.", str)),
+            ("num".to_owned(), Value::form("num", &["string"], "Parses a string into a number, or a decimal in decimal mode.", num)),
+            ("sum".to_owned(), Value::form("sum", &["name = range", "body"], "The sum of body over each item of a list, tuple or range bound to name.", sum)),
+            ("Σ".to_owned(), Value::form("Σ", &["name = range", "body"], "The sum of body over each item of a list, tuple or range bound to name.", sum)),
+            ("prod".to_owned(), Value::form("prod", &["name = range", "body"], "The product of body over each item of a list, tuple or range bound to name.", prod)),
+            ("Π".to_owned(), Value::form("Π", &["name = range", "body"], "The product of body over each item of a list, tuple or range bound to name.", prod)),
+            ("divmod".to_owned(), Value::native("divmod", &["x", "y"], "The floored quotient and remainder of dividing x by y, as a tuple.", divmod)),
+            ("polar".to_owned(), Value::native("polar", &["x", "y"], "The distance from the origin and the angle of a point, as a tuple.", polar)),
+            ("simplify".to_owned(), Value::native("simplify", &["f"], "Folds constants and removes identities such as adding zero in function arithmetic.", simplify)),
+            ("derivative".to_owned(), Value::native("derivative", &["f"], "The derivative of a function built by arithmetic on id and functions with known derivatives.", derivative)),
+            ("partial".to_owned(), Value::native("partial", &["f"], "A copy of f that, called with too few arguments, returns a function awaiting the rest.", partial)),
+            ("id".to_owned(), Value::Function(Rc::new(Function {
+                doc: Some("The identity function, for building functions such as id ^ 2 + 1.".to_owned()),
+                ..Function::lifted(Lifted::Argument)
+            }))),
+            ("list".to_owned(), Value::native("list", &["x"], "The items of a list, tuple or range as a list.", list)),
+            ("map".to_owned(), Value::native("map", &["f", "list"], "Applies f to each item of a list, tuple or range.", map)),
+            ("filter".to_owned(), Value::native("filter", &["p", "list"], "The items of a list, tuple or range for which p is true.", filter)),
+            ("fold".to_owned(), Value::native("fold", &["f", "init", "list"], "Combines init with each item of a list, tuple or range in turn by f(acc, item).", fold)),
+            ("iterate".to_owned(), Value::native("iterate", &["f", "x0", "n"], "The list of x0, f(x0), f(f(x0)) and so on up to n applications of f.", iterate)),
+            ("fixpoint".to_owned(), Value::native("fixpoint", &["f", "x0", "tol", "limit?"], "Applies f from x0 until the result changes by at most tol, within limit steps (1000 by default).", fixpoint)),
+            ("tabulate".to_owned(), Value::native("tabulate", &["f", "a", "b", "step?"], "The values of f from a to b inclusive in steps of step (1 by default).", tabulate)),
+            ("true".to_owned(), Value::Boolean(true)),
+            ("false".to_owned(), Value::Boolean(false)),
+        ]);
+        table.register("upper", |string: String| string.to_uppercase());
+        table.register("lower", |string: String| string.to_lowercase());
         Ok(Self {
-            tree: Parser::from(string).parse()?,
-            table: HashMap::from([
-                ("+".to_owned(), Value::Function(Rc::new(plus))),
-                ("-".to_owned(), Value::Function(Rc::new(minus))),
-                ("*".to_owned(), Value::Function(Rc::new(star))),
-                ("/".to_owned(), Value::Function(Rc::new(slash))),
-                ("%".to_owned(), Value::Function(Rc::new(percent))),
-                ("^".to_owned(), Value::Function(Rc::new(caret))),
-            ]),
+            program,
+            table,
+            mode: Mode::Float,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(string: &str) -> String {
+        show(Expression::try_from(string).unwrap().eval())
+    }
+    fn eval_decimal(string: &str, precision: usize) -> String {
+        let mut expr = Expression::try_from(string).unwrap();
+        expr.mode = Mode::Decimal(Context::new(precision, Rounding::HalfEven));
+        show(expr.eval())
+    }
+    fn show(result: Result<Value, ExpressionError>) -> String {
+        match result {
+            Ok(value) => format!("{:?}", value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn recursion_limit_protects_the_default_stack() {
+        let program = "f(n) = if n == 0 then 0 else 1 + f(n - 1); f(N)";
+        assert_eq!(eval(&program.replace('N', "10")), "Number(10)");
+        assert_eq!(eval(&program.replace('N', "100000")), "recursion limit exceeded in \"f\"");
+        let result = std::thread::spawn(move || eval(&program.replace('N', "200"))).join().unwrap();
+        assert!(result == "Number(200)" || result == "recursion limit exceeded in \"f\"", "{}", result);
+    }
+
+    #[test]
+    fn raised_stack_limit_allows_the_deepest_recursion() {
+        let result = std::thread::Builder::new().stack_size(128 << 20).spawn(|| {
+            set_stack_limit(96 << 20);
+            let program = "f(n) = if n == 0 then 0 else 1 + f(n - 1); f(N)";
+            (eval(&program.replace('N', "999")), eval(&program.replace('N', "1000")))
+        }).unwrap().join().unwrap();
+        assert_eq!(result.0, "Number(999)");
+        assert_eq!(result.1, "recursion limit exceeded in \"f\"");
+    }
+
+    #[test]
+    fn literals_keep_their_float_and_exact_values() {
+        assert_eq!(eval("0.1 + 0.2"), "Number(0.30000000000000004)");
+        assert_eq!(eval_decimal("0.1 + 0.2", 20), "Decimal(0.3)");
+        assert_eq!(eval("f(n) = n * 0.5; f(3) + f(3)"), "Number(3)");
+    }
+
+    #[test]
+    fn definitions_see_only_earlier_names_unless_grouped() {
+        assert_eq!(eval("f(x) = x * k; k = 2; f(3)"), "function call failure: undefined identifier \"k\"");
+        assert_eq!(eval("k = 2; f(x) = x * k; k = 3; f(3)"), "Number(6)");
+        let parity = "rec {\n  even(0) = true\n  even(n) = odd(n - 1)\n  odd(0) = false\n  odd(n) = even(n - 1)\n}\n";
+        assert_eq!(eval(&format!("{}even(10)", parity)), "Boolean(true)");
+        assert_eq!(eval(&format!("{}odd(10)", parity)), "Boolean(false)");
+        assert_eq!(eval("rec { fib(0) = 0; fib(1) = 1; fib(n) = fib(n - 1) + fib(n - 2) }; fib(20)"), "Number(6765)");
+    }
+
+    #[test]
+    fn decimal_matrices_are_rejected_rather_than_multiplied_item_by_item() {
+        let error = "function call failure: matrices of decimals are not supported, found List([List([Decimal(1), Decimal(2)]), List([Decimal(3), Decimal(4)])])";
+        assert_eq!(eval_decimal("[[1, 2], [3, 4]] * [[5, 6], [7, 8]]", 20), error);
+        assert_eq!(eval_decimal("[[1, 2], [3, 4]] * [1, 1]", 20), error);
+        assert_eq!(eval_decimal("det([[1, 2], [3, 4]])", 20), error);
+        assert_eq!(eval_decimal("transpose([[1, 2], [3, 4]])", 20), error);
+        assert_eq!(eval_decimal("[[1, 2], [3, 4]] * 2", 20), "List([List([Decimal(2), Decimal(4)]), List([Decimal(6), Decimal(8)])])");
+        assert_eq!(eval_decimal("[1, 2] * [3, 4]", 20), "List([Decimal(3), Decimal(8)])");
+        assert_eq!(eval("[[1, 2], [3, 4]] * [[5, 6], [7, 8]]"), "Matrix([[19, 22], [43, 50]])");
+    }
+
+    #[test]
+    fn format_pads_floats_past_their_digits() {
+        assert_eq!(eval("format(2.5, 3)"), "String(\"2.500\")");
+        assert_eq!(eval("len(format(2, 65536))"), "Number(65538)");
+        assert_eq!(eval("substr(format(2, 1000000), 999990)"), "String(\"000000000000\")");
+        assert_eq!(eval("substr(format(5e-324, 1100), 1072, 8)"), "String(\"56250000\")");
+        assert_eq!(eval("format(0 / 0, 70000)"), "String(\"NaN\")");
+        assert_eq!(eval("len(format(2, 1000001))"), "function call failure: invalid arguments [Number(2), Number(1000001)] to format(x, digits)");
+    }
+
+
+    #[test]
+    fn ranges_count_their_items_exactly() {
+        assert_eq!(eval("len(0..1:0.1)"), "Number(11)");
+        assert_eq!(eval("len(0..0.3:0.1)"), "Number(3)");
+        assert_eq!(eval_decimal("len(0..0.3:0.1)", 20), "Number(4)");
+        assert_eq!(eval("len(10..1:-3)"), "Number(4)");
+        assert_eq!(eval("len(5..1)"), "Number(0)");
+        assert_eq!(eval("(0..9e18)[-1]"), "Number(9000000000000000000)");
+        assert_eq!(eval("(1..10)[-11]"), "invalid index Number(-11) into Range(1..10)");
+        assert!(eval("0..1e300").starts_with("invalid range"));
+        assert!(eval_decimal("0..9223372036854775807", 30).starts_with("invalid range"));
+        assert_eq!(eval_decimal("len(0..9223372036854775806)", 30), "Number(9223372036854776000)");
+    }
+
+    #[test]
+    fn ranges_index_and_iterate_lazily() {
+        assert_eq!(eval("1..10:2"), "Range(1..10:2)");
+        assert_eq!(eval("(1..10:2)[2]"), "Number(5)");
+        assert_eq!(eval("(10..1:-3)[-1]"), "Number(1)");
+        assert_eq!(eval("list(0..1:0.25)"), "List([Number(0), Number(0.25), Number(0.5), Number(0.75), Number(1)])");
+        assert_eq!(eval_decimal("(0..1:0.1)[3]", 20), "Decimal(0.3)");
+        assert_eq!(eval("(1..1e15)[999999999999]"), "Number(1000000000000)");
+        assert_eq!(eval("1..\"a\""), "invalid range with start, end and step [Number(1), String(\"a\"), Number(1)]");
+    }
+
+    #[test]
+    fn tabulate_counts_its_steps_like_a_range() {
+        assert_eq!(eval("tabulate(x -> x^2, 0, 1, 0.25)"), "List([Number(0), Number(0.0625), Number(0.25), Number(0.5625), Number(1)])");
+        assert_eq!(eval("len(tabulate(x -> x, 0, 0.3, 0.1)) == len(0..0.3:0.1)"), "Boolean(true)");
+        assert_eq!(eval_decimal("tabulate(x -> x, 0, 1, 1/3)", 5), "List([Decimal(0), Decimal(0.33333), Decimal(0.66666), Decimal(0.99999)])");
+        assert_eq!(eval("tabulate(x -> x, 3, 1)"), "List([])");
+        assert!(eval("tabulate(x -> x, 0, 1e30)").ends_with("to tabulate(f, a, b, step?)"));
+    }
+
+    #[test]
+    fn higher_order_built_ins_take_functions() {
+        assert_eq!(eval("map(x -> x^2, 1..3)"), "List([Number(1), Number(4), Number(9)])");
+        assert_eq!(eval("filter(x -> x % 2 == 0, 1..6)"), "List([Number(2), Number(4), Number(6)])");
+        assert_eq!(eval("fold((acc, x) -> acc + x, 0, [1, 2, 3])"), "Number(6)");
+        assert_eq!(eval("iterate(x -> x * 2, 1, 4)"), "List([Number(1), Number(2), Number(4), Number(8), Number(16)])");
+        assert_eq!(eval("fixpoint(x -> (x + 2 / x) / 2, 1, 1e-12)"), "Number(1.414213562373095)");
+        assert_eq!(eval("fixpoint(x -> x + 1, 0, 1e-12, 10)"), "function call failure: no convergence after 10 iterations, last at Number(10)");
+        assert_eq!(eval("filter(x -> 1, [1])"), "function call failure: expected a boolean but found Number(1)");
+        assert_eq!(eval("map(1, [1])"), "function call failure: invalid arguments [Number(1), List([Number(1)])] to map(f, list)");
+    }
+
+    #[test]
+    fn comparisons_and_logic_give_booleans() {
+        assert_eq!(eval("1 < 2 && 2 <= 2"), "Boolean(true)");
+        assert_eq!(eval("!(1 == 1) || 3 != 3"), "Boolean(false)");
+        assert_eq!(eval("\"a\" == \"a\""), "Boolean(true)");
+        assert_eq!(eval_decimal("0.1 + 0.2 == 0.3", 20), "Boolean(true)");
+        assert_eq!(eval("1 && true"), "expected a boolean but found Number(1)");
+        assert_eq!(eval("1 < \"a\""), "function call failure: invalid arguments [Number(1), String(\"a\")] to <(x, y)");
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        assert_eq!(eval("false && 1 / \"a\""), "Boolean(false)");
+        assert_eq!(eval("true || undefined_name"), "Boolean(true)");
+        assert_eq!(eval("true && 1 < 0"), "Boolean(false)");
+    }
+
+    #[test]
+    fn conditionals_evaluate_only_the_branch_taken() {
+        assert_eq!(eval("if 1 < 2 then 10 else 1 / \"a\""), "Number(10)");
+        assert_eq!(eval("if 1 > 2 then undefined_name else 20"), "Number(20)");
+        assert_eq!(eval("if 1 then 2 else 3"), "expected a boolean but found Number(1)");
+    }
+
+    #[test]
+    fn forms_receive_their_arguments_unevaluated() {
+        let mut expr = Expression::try_from("first(2, undefined_name) + first(1 / \"a\", 0)").unwrap();
+        expr.table.insert("first".to_owned(), Value::form("first", &["x", "y"], "Whichever argument evaluates.", |args| {
+            Ok(args.iter().find_map(|arg| arg.eval().ok()).unwrap_or(Value::Number(0.0)))
+        }));
+        assert_eq!(show(expr.eval()), "Number(2)");
+        assert_eq!(eval_decimal("num(\"0.1\") + num(\"0.2\")", 20), "Decimal(0.3)");
+        assert_eq!(eval("sum(1, 2)"), "function call failure: expected a call of the form sum(name = range, body)");
+    }
+
+    #[test]
+    fn lambdas_are_first_class_closures() {
+        assert_eq!(eval("(x -> x^2)(3)"), "Number(9)");
+        assert_eq!(eval("((x, y) -> x + y)(1, 2)"), "Number(3)");
+        assert_eq!(eval("f = x -> y -> x + y; f(1)(2)"), "Number(3)");
+        assert_eq!(eval("k = 2; g = x -> x * k; k = 5; g(3)"), "Number(6)");
+        assert_eq!(eval("() -> 1"), "Function(<anonymous>())");
+        assert_eq!(eval("(x -> x)(1, 2)"), "function call failure: <anonymous>(x) expected 1 arguments but found 2");
+    }
+
+    #[test]
+    fn definitions_are_returned_to_callers() {
+        assert_eq!(eval("f(x, y) = x * y; f(2, 3)"), "Number(6)");
+        assert_eq!(eval("f(x) = x^2; f(1, 2)"), "function call failure: f(x) expected 1 arguments but found 2");
+        let (value, defined) = Expression::try_from("f(x) = x^2").unwrap().eval_bindings().unwrap();
+        assert_eq!(format!("{:?}", value), "Function(f(x))");
+        let Some(Value::Function(func)) = defined.get("f") else {
+            panic!("f is not defined");
+        };
+        assert_eq!(format!("{:?}", func.call(vec![Value::Number(4.0)]).unwrap()), "Number(16)");
+    }
+
+    #[test]
+    fn programs_run_statements_in_order() {
+        assert_eq!(eval("x = 1\ny = x + 1\n(x\n + y)"), "Number(3)");
+        assert_eq!(eval("x = 1\n+ 2"), "Number(2)");
+        assert_eq!(eval("x = 1;;\n\nx = x + 1;\n"), "Number(2)");
+        assert!(Expression::try_from("x = 1 y = 2").is_err());
+        let (_, defined) = Expression::try_from("x = 1; y = x * 2").unwrap().eval_bindings().unwrap();
+        assert!(matches!(defined.get("y"), Some(Value::Number(y)) if *y == 2.0));
+    }
+
+    #[test]
+    fn let_bindings_are_local_and_sequential() {
+        assert_eq!(eval("let x = 2, y = x + 1 in x * y"), "Number(6)");
+        assert_eq!(eval("let x = 1 in let x = x + 1 in x"), "Number(2)");
+        assert_eq!(eval("x * 2 where x = 3"), "Number(6)");
+        assert_eq!(eval("(let x = 2 in x) + x"), "undefined identifier \"x\"");
+        assert_eq!(eval("let x = 1 / \"a\" in 2"), "binding failure \"x\": function call failure: invalid arguments [Number(1), String(\"a\")] to /(x, y)");
+    }
+
+    #[test]
+    fn lists_index_slice_and_broadcast() {
+        assert_eq!(eval("[1, 2, 3][0] + [1, 2, 3][-1]"), "Number(4)");
+        assert_eq!(eval("[1, 2, 3][3]"), "invalid index Number(3) into List([Number(1), Number(2), Number(3)])");
+        assert_eq!(eval("[1, 2, 3][1:]"), "List([Number(2), Number(3)])");
+        assert_eq!(eval("[1, 2, 3][:-1]"), "List([Number(1), Number(2)])");
+        assert_eq!(eval("len([1, [2, 3]])"), "Number(2)");
+        assert_eq!(eval("[1, 2] + [3, 4]"), "List([Number(4), Number(6)])");
+        assert_eq!(eval("[1, 2] + [3]"), "function call failure: invalid arguments [List([Number(1), Number(2)]), List([Number(3)])] to +(x, y?)");
+        assert_eq!(eval("[]"), "List([])");
+    }
+
+    #[test]
+    fn tuples_return_several_values_to_destructure() {
+        assert_eq!(eval("(1, \"a\", true)"), "Tuple([Number(1), String(\"a\"), Boolean(true)])");
+        assert_eq!(eval("(1,)"), "Tuple([Number(1)])");
+        assert_eq!(eval("()"), "Tuple([])");
+        assert_eq!(eval("f(x) = (x, x^2); (a, b) = f(3); a + b"), "Number(12)");
+        assert_eq!(eval("divmod(7, 2)"), "Tuple([Number(3), Number(1)])");
+        assert_eq!(eval("(1, 2)[1]"), "Number(2)");
+        assert_eq!(eval("(a, b) = (1, 2, 3)"), "cannot destructure Tuple([Number(1), Number(2), Number(3)]) into 2 names");
+        assert_eq!(eval("(a, b) = 5"), "cannot destructure Number(5) into 2 names");
+    }
+
+    #[test]
+    fn records_have_fields() {
+        assert_eq!(eval("{x: 1, y: 2}.y"), "Number(2)");
+        assert_eq!(eval("r = {f: x -> x + 1}; r.f(2)"), "Number(3)");
+        assert_eq!(eval("{}"), "Record({})");
+        assert_eq!(eval("{x: 1}.z"), "undefined field \"z\" in Record({x: Number(1)})");
+        assert_eq!(eval("{x: 1}.x.y"), "undefined field \"y\" in Number(1)");
+    }
+
+    #[test]
+    fn strings_join_measure_and_format() {
+        assert_eq!(eval("\"a\\\"b\\n\""), "String(\"a\\\"b\\n\")");
+        assert_eq!(eval("\"ab\" + \"cd\""), "String(\"abcd\")");
+        assert_eq!(eval("len(\"héllo\")"), "Number(5)");
+        assert_eq!(eval("substr(\"héllo\", 1, 3)"), "String(\"éll\")");
+        assert_eq!(eval("substr(\"abc\", 5)"), "String(\"\")");
+        assert_eq!(eval("\"abc\" < \"abd\""), "Boolean(true)");
+        assert_eq!(eval("str(1.5)"), "String(\"1.5\")");
+        assert_eq!(eval_decimal("str(1 / 3)", 5), "String(\"0.33333\")");
+        assert_eq!(eval_decimal("format(1 / 3, 2)", 20), "String(\"0.33\")");
+    }
+
+    #[test]
+    fn imports_load_modules_relative_to_the_importer() {
+        let dir = std::env::temp_dir().join(format!("mexpr-imports-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.mx"), "import \"lib/geo.mx\"\nuse geo\nsquare_k = square(k)").unwrap();
+        fs::write(dir.join("lib/geo.mx"), "square(x) = x^2\nk = 3").unwrap();
+        fs::write(dir.join("a.mx"), "import \"b.mx\"").unwrap();
+        fs::write(dir.join("b.mx"), "import \"a.mx\"").unwrap();
+        assert_eq!(eval(&format!("import {:?}; main.square_k", dir.join("main.mx"))), "Number(9)");
+        assert!(eval(&format!("import {:?}", dir.join("a.mx"))).contains("import cycle"));
+        assert!(eval(&format!("import {:?}", dir.join("missing.mx"))).starts_with("failed to import"));
+        assert_eq!(eval("m = {f: x -> x + 1}; use m; f(1)"), "Number(2)");
+        assert_eq!(eval("use m"), "undefined identifier \"m\"");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn functions_know_their_name_arity_and_documentation() {
+        let func = Rc::new(Function::native("clip", &["x", "low?"], "Clips x.", |args| Ok(args[0].clone())));
+        assert_eq!(func.arity, Some(1..=2));
+        assert_eq!(func.to_string(), "clip(x, low?): Clips x.");
+        assert_eq!(func.apply(vec![Value::Number(1.0)]).signature(), "clip(low?)");
+        assert_eq!(Function::native("all", &["x", "rest..."], "", |args| Ok(args[0].clone())).arity, Some(2..=usize::MAX));
+        assert_eq!(eval("f(x, y) = x; f"), "Function(f(x, y))");
+        assert_eq!(eval("(+)"), "Function(+(x, y?))");
+        assert_eq!(eval("x -> x"), "Function(<anonymous>(x))");
+        assert_eq!(eval("f(x, y) = x; f(1, 2, 3)"), "function call failure: f(x, y) expected 2 arguments but found 3");
+    }
+
+    #[test]
+    fn sections_and_partial_application_await_the_rest() {
+        assert_eq!(eval("(2 *)(5) + (/ 2)(8)"), "Number(14)");
+        assert_eq!(eval("(2 ^)(3)"), "Number(8)");
+        assert_eq!(eval("(- 1)"), "Number(-1)");
+        assert_eq!(eval("f(x, y) = x - y; g = partial(f); g(10)(3)"), "Number(7)");
+        assert_eq!(eval("f(x, y) = x - y; partial(f)(10)"), "Function(f(y))");
+        assert_eq!(eval("partial(1)"), "function call failure: invalid arguments [Number(1)] to partial(f)");
+    }
+
+    #[test]
+    fn composition_and_pipes_chain_functions() {
+        assert_eq!(eval("f = (x -> x + 1) ∘ (x -> x * 2); f(3)"), "Number(7)");
+        assert_eq!(eval("3 |> (x -> x * 2) |> (x -> x + 1)"), "Number(7)");
+        assert_eq!(eval("[1, 2, 3] |> len"), "Number(3)");
+        assert_eq!(eval("((x -> x + 1) ∘ id)(3)"), "Number(4)");
+        assert_eq!(eval("3 |> 4"), "function call failure: invalid function Number(4)");
+        assert_eq!(eval("(x -> x + 1) ∘ 5"), "function call failure: invalid arguments [Function(<anonymous>(x)), Number(5)] to ∘(f, g)");
+    }
+
+    #[test]
+    fn sums_and_products_bind_their_index() {
+        assert_eq!(eval("sum(k = 1..4, k^2) + Σ(k = 1..4, k)"), "Number(40)");
+        assert_eq!(eval("prod(k = 1..5, k)"), "Number(120)");
+        assert_eq!(eval("sum(k = [], k) + Π(k = [], k)"), "Number(1)");
+        assert_eq!(eval("n = 3; sum(k = 1..n, sum(j = 1..k, j))"), "Number(10)");
+        assert_eq!(eval("k = 100; sum(k = 1..2, k) + k"), "Number(103)");
+        assert_eq!(eval_decimal("sum(k = 1..10, 1 / 10)", 30), "Decimal(1)");
+        assert_eq!(eval("sum(k = 5, k)"), "function call failure: invalid arguments [Number(5)] to sum(name = range, body)");
+    }
+
+    #[test]
+    fn piecewise_branches_report_where_none_matched() {
+        assert_eq!(eval("f(x) = piecewise { x < 0: -x; else: x }; f(-3) + f(2)"), "Number(5)");
+        assert_eq!(eval("f(x) = piecewise {\n  x < 0: -1\n  x > 0: 1\n}\nf(-2)"), "Number(-1)");
+        assert_eq!(eval("f(x) = piecewise { x < 0: -1; x > 0: 1 }; f(0)"), "function call failure: no branch of the piecewise expression at line 1, column 8 matches");
+        assert_eq!(eval("x = 1\n\ny = piecewise { x > 1: 0 }"), "no branch of the piecewise expression at line 3, column 5 matches");
+        assert_eq!(eval("piecewise { 1: 2 }"), "expected a boolean but found Number(1)");
+        assert!(Expression::try_from("piecewise { else: 1; 2: 3 }").is_err());
+    }
+
+    #[test]
+    fn pure_named_functions_are_memoised() {
+        let calls = Rc::new(Cell::new(0));
+        let count = |pure: bool| {
+            let calls = calls.clone();
+            let body = move |args: Vec<Value>| {
+                calls.set(calls.get() + 1);
+                Ok(args[0].clone())
+            };
+            Value::Function(Rc::new(Function { pure, ..Function::native("count", &["x"], "", body) }))
+        };
+        let run = |string: &str, pure: bool| {
+            let mut expr = Expression::try_from(string).unwrap();
+            expr.table.insert("count".to_owned(), count(pure));
+            show(expr.eval())
+        };
+        assert_eq!(run("f(n) = count(n); f(1) + f(1) + f(2)", true), "Number(4)");
+        assert_eq!(calls.replace(0), 2);
+        assert_eq!(run("f(n) = count(n); f(1) + f(1)", false), "Number(2)");
+        assert_eq!(calls.replace(0), 2);
+        assert_eq!(run("(n -> count(n))(1) + (n -> count(n))(1)", true), "Number(2)");
+        assert_eq!(calls.replace(0), 2);
+        assert_eq!(run("f(xs) = count(len(xs)); f([1]) + f([1])", true), "Number(2)");
+        assert_eq!(calls.replace(0), 2);
+        assert_eq!(run("rec { f(n) = count(n) + g(n); g(n) = 0 }; f(1) + f(1)", true), "Number(2)");
+        assert_eq!(calls.replace(0), 1);
+        assert_eq!(run("rec { f(n) = g(n); g(n) = count(n) }; f(1) + f(1)", false), "Number(2)");
+        assert_eq!(calls.replace(0), 2);
+    }
+}
@@ -1,3 +1,9 @@
 pub mod token;
 pub mod parse;
-pub mod expr;
\ No newline at end of file
+pub mod expr;
+pub mod decimal;
+pub mod matrix;
+pub mod stdlib;
+pub mod register;
+pub mod lifted;
+pub mod range;
\ No newline at end of file
@@ -0,0 +1,335 @@
+use crate::{decimal::Decimal, expr::*};
+use std::{error::Error, fmt::Display, ops, rc::Rc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+}
+impl Operator {
+    pub fn apply(self, args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+        match self {
+            Self::Plus => plus(args),
+            Self::Minus => minus(args),
+            Self::Star => star(args),
+            Self::Slash => slash(args),
+            Self::Percent => percent(args),
+            Self::Caret => caret(args),
+        }
+    }
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Plus | Self::Minus => 4,
+            Self::Star | Self::Slash | Self::Percent => 5,
+            Self::Caret => 7,
+        }
+    }
+}
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Star => "*",
+            Self::Slash => "/",
+            Self::Percent => "%",
+            Self::Caret => "^",
+        })
+    }
+}
+
+/// Arithmetic over functions, kept as a tree so that it can be shown, simplified and differentiated.
+#[derive(Clone)]
+pub enum Lifted {
+    Constant(Value),
+    /// The argument itself, i.e. the identity function.
+    Argument,
+    Function(Rc<Function>),
+    Unary(Operator, Rc<Lifted>),
+    Binary(Operator, Rc<Lifted>, Rc<Lifted>),
+    /// The outer function applied to the result of the inner one.
+    Compose(Rc<Lifted>, Rc<Lifted>),
+    /// A function applied to the results of several trees, such as `f(sin, 2)`.
+    Apply(Rc<Function>, Vec<Lifted>),
+}
+impl Lifted {
+    /// The tree of a function argument to an operator, which is inlined when it is lifted itself.
+    pub fn from_value(value: &Value) -> Self {
+        match value {
+            Value::Function(func) => match &func.body {
+                Body::Lifted(tree) => tree.clone(),
+                Body::Native(_) => Self::Function(func.clone()),
+            },
+            value => Self::Constant(value.clone()),
+        }
+    }
+    /// The tree as a function value, which is the function itself when the tree is a lone one.
+    pub fn into_value(self) -> Value {
+        match self {
+            Self::Function(func) => Value::Function(func),
+            tree => Value::Function(Rc::new(Function::lifted(tree))),
+        }
+    }
+    pub fn pow(self, exponent: Self) -> Self {
+        Self::Binary(Operator::Caret, Rc::new(self), Rc::new(exponent))
+    }
+    /// Whether every function in the tree is pure.
+    pub fn pure(&self) -> bool {
+        match self {
+            Self::Constant(_) | Self::Argument => true,
+            Self::Function(func) => func.pure,
+            Self::Unary(_, operand) => operand.pure(),
+            Self::Binary(_, lhs, rhs) | Self::Compose(lhs, rhs) => lhs.pure() && rhs.pure(),
+            Self::Apply(func, operands) => func.pure && operands.iter().all(Self::pure),
+        }
+    }
+    pub fn eval(&self, args: &[Value]) -> Result<Value, Box<dyn Error>> {
+        match self {
+            Self::Constant(value) => Ok(value.clone()),
+            Self::Argument => match args {
+                [arg] => Ok(arg.clone()),
+                _ => Err(Box::new(InvalidArguments::new(args.to_vec()))),
+            },
+            Self::Function(func) => func.call(args.to_vec()),
+            Self::Unary(operator, operand) => operator.apply(vec![operand.eval(args)?]),
+            Self::Binary(operator, lhs, rhs) => operator.apply(vec![lhs.eval(args)?, rhs.eval(args)?]),
+            Self::Compose(outer, inner) => outer.eval(&[inner.eval(args)?]),
+            Self::Apply(func, operands) => func.call(operands.iter().map(|operand| operand.eval(args)).collect::<Result<_, _>>()?),
+        }
+    }
+    /// Folds constants and removes additions of zero, multiplications by one and the like.
+    pub fn simplify(&self) -> Self {
+        match self {
+            Self::Unary(operator, operand) => match (operator, operand.simplify()) {
+                (Operator::Plus, operand) => operand,
+                (Operator::Minus, Self::Unary(Operator::Minus, operand)) => (*operand).clone(),
+                (operator, Self::Constant(value)) => match operator.apply(vec![value.clone()]) {
+                    Ok(value) => Self::Constant(value),
+                    Err(_) => Self::Unary(*operator, Rc::new(Self::Constant(value))),
+                },
+                (operator, operand) => Self::Unary(*operator, Rc::new(operand)),
+            },
+            Self::Binary(operator, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.simplify(), rhs.simplify());
+                if let (Self::Constant(value1), Self::Constant(value2)) = (&lhs, &rhs) {
+                    if let Ok(value) = operator.apply(vec![value1.clone(), value2.clone()]) {
+                        return Self::Constant(value);
+                    }
+                }
+                match (operator, lhs, rhs) {
+                    (Operator::Plus, lhs, rhs) if lhs.is(0.0) => rhs,
+                    (Operator::Plus | Operator::Minus, lhs, rhs) if rhs.is(0.0) => lhs,
+                    (Operator::Minus, lhs, rhs) if lhs.is(0.0) => -rhs,
+                    (Operator::Plus, lhs, Self::Unary(Operator::Minus, rhs)) => lhs - (*rhs).clone(),
+                    (Operator::Minus, lhs, Self::Unary(Operator::Minus, rhs)) => lhs + (*rhs).clone(),
+                    (Operator::Star, lhs, Self::Unary(Operator::Minus, rhs)) => -(lhs * (*rhs).clone()),
+                    (Operator::Star, lhs, _) if lhs.is(0.0) => lhs,
+                    (Operator::Star, _, rhs) if rhs.is(0.0) => rhs,
+                    (Operator::Star, lhs, rhs) if lhs.is(1.0) => rhs,
+                    (Operator::Star | Operator::Slash | Operator::Caret, lhs, rhs) if rhs.is(1.0) => lhs,
+                    (Operator::Caret, _, rhs) if rhs.is(0.0) => Self::Constant(Value::Number(1.0)),
+                    (operator, lhs, rhs) => Self::Binary(*operator, Rc::new(lhs), Rc::new(rhs)),
+                }
+            },
+            Self::Compose(outer, inner) => match (outer.simplify(), inner.simplify()) {
+                (Self::Argument, tree) | (tree, Self::Argument) => tree,
+                (Self::Constant(value), _) => Self::Constant(value),
+                (outer, Self::Constant(value)) => match outer.eval(std::slice::from_ref(&value)) {
+                    Ok(value) => Self::Constant(value),
+                    Err(_) => Self::Compose(Rc::new(outer), Rc::new(Self::Constant(value))),
+                },
+                (outer, inner) => Self::Compose(Rc::new(outer), Rc::new(inner)),
+            },
+            Self::Apply(func, operands) => {
+                let operands: Vec<_> = operands.iter().map(Self::simplify).collect();
+                let constants = operands.iter().map(|operand| match operand {
+                    Self::Constant(value) => Some(value.clone()),
+                    _ => None,
+                }).collect::<Option<Vec<_>>>();
+                match constants.map(|values| func.call(values)) {
+                    Some(Ok(value)) => Self::Constant(value),
+                    _ => Self::Apply(func.clone(), operands),
+                }
+            },
+            tree => tree.clone(),
+        }
+    }
+    fn is(&self, num: f64) -> bool {
+        match self {
+            Self::Constant(Value::Number(value)) => *value == num,
+            Self::Constant(Value::Decimal(value)) => value.to_f64() == num,
+            _ => false,
+        }
+    }
+    /// The derivative with respect to the argument, if every function in the tree has a known one.
+    pub fn derivative(&self) -> Option<Self> {
+        match self {
+            Self::Constant(_) => Some(Self::Constant(Value::Number(0.0))),
+            Self::Argument => Some(Self::Constant(Value::Number(1.0))),
+            Self::Function(func) => func.derivative.map(|derivative| derivative()),
+            Self::Unary(operator, operand) => Some(Self::Unary(*operator, Rc::new(operand.derivative()?))),
+            Self::Compose(outer, inner) => Some(Self::Compose(Rc::new(outer.derivative()?), inner.clone()) * inner.derivative()?),
+            Self::Apply(func, operands) => match operands.as_slice() {
+                [operand] => Some(Self::Compose(Rc::new(func.derivative?()), Rc::new(operand.clone())) * operand.derivative()?),
+                _ => None,
+            },
+            Self::Binary(operator, lhs, rhs) => {
+                let (lhs, rhs) = ((**lhs).clone(), (**rhs).clone());
+                match operator {
+                    Operator::Plus => Some(lhs.derivative()? + rhs.derivative()?),
+                    Operator::Minus => Some(lhs.derivative()? - rhs.derivative()?),
+                    Operator::Star => Some(lhs.derivative()? * rhs.clone() + lhs * rhs.derivative()?),
+                    Operator::Slash => Some((lhs.derivative()? * rhs.clone() - lhs * rhs.derivative()?) / rhs.pow(Self::Constant(Value::Number(2.0)))),
+                    Operator::Percent => match rhs {
+                        Self::Constant(_) => lhs.derivative(),
+                        _ => None,
+                    },
+                    Operator::Caret => match (&lhs, &rhs) {
+                        (_, Self::Constant(exponent)) => {
+                            let reduced = minus(vec![exponent.clone(), Value::Number(1.0)]).ok()?;
+                            Some(rhs.clone() * lhs.clone().pow(Self::Constant(reduced)) * lhs.derivative()?)
+                        },
+                        (Self::Constant(base), _) => {
+                            let ln = match base {
+                                Value::Number(num) => Value::Number(num.ln()),
+                                Value::Decimal(num) => Value::Decimal(Decimal::ln(num)?),
+                                _ => return None,
+                            };
+                            Some(self.clone() * Self::Constant(ln) * rhs.derivative()?)
+                        },
+                        _ => None,
+                    },
+                }
+            },
+        }
+    }
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Unary(_, _) => 6,
+            Self::Binary(operator, _, _) => operator.precedence(),
+            Self::Compose(_, _) => 8,
+            Self::Constant(Value::Number(num)) if *num < 0.0 => 6,
+            Self::Constant(Value::Decimal(num)) if num.is_negative() => 6,
+            _ => 9,
+        }
+    }
+    fn fmt_with(&self, f: &mut std::fmt::Formatter<'_>, precedence: u8) -> std::fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+impl Display for Lifted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Constant(Value::Number(num)) => write!(f, "{}", num),
+            Self::Constant(Value::Decimal(num)) => write!(f, "{}", num),
+            Self::Constant(value) => write!(f, "{:?}", value),
+            Self::Argument => write!(f, "id"),
+            Self::Function(func) => write!(f, "{}", func.name.as_deref().unwrap_or("<anonymous>")),
+            Self::Unary(operator, operand) => {
+                write!(f, "{}", operator)?;
+                operand.fmt_with(f, 6)
+            },
+            Self::Compose(outer, inner) => {
+                outer.fmt_with(f, 9)?;
+                write!(f, " ∘ ")?;
+                inner.fmt_with(f, 8)
+            },
+            Self::Apply(func, operands) => {
+                write!(f, "{}(", func.name.as_deref().unwrap_or("<anonymous>"))?;
+                for (i, operand) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", operand)?;
+                }
+                write!(f, ")")
+            },
+            Self::Binary(Operator::Caret, lhs, rhs) => {
+                lhs.fmt_with(f, 8)?;
+                write!(f, " ^ ")?;
+                rhs.fmt_with(f, 6)
+            },
+            Self::Binary(operator, lhs, rhs) => {
+                lhs.fmt_with(f, operator.precedence())?;
+                write!(f, " {} ", operator)?;
+                rhs.fmt_with(f, operator.precedence() + 1)
+            },
+        }
+    }
+}
+
+impl ops::Add for Lifted {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::Binary(Operator::Plus, Rc::new(self), Rc::new(rhs))
+    }
+}
+impl ops::Sub for Lifted {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::Binary(Operator::Minus, Rc::new(self), Rc::new(rhs))
+    }
+}
+impl ops::Mul for Lifted {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::Binary(Operator::Star, Rc::new(self), Rc::new(rhs))
+    }
+}
+impl ops::Div for Lifted {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        Self::Binary(Operator::Slash, Rc::new(self), Rc::new(rhs))
+    }
+}
+impl ops::Neg for Lifted {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::Unary(Operator::Minus, Rc::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib;
+    use super::*;
+
+    fn eval(string: &str) -> String {
+        let mut expr = Expression::try_from(string).unwrap();
+        stdlib::install(&mut expr.table, expr.mode);
+        match expr.eval() {
+            Ok(value) => format!("{:?}", value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn arithmetic_on_functions_shows_its_structure() {
+        assert_eq!(eval("sin + cos"), "Function(sin + cos)");
+        assert_eq!(eval("2 * sin"), "Function(2 * sin)");
+        assert_eq!(eval("-sin"), "Function(-sin)");
+        assert_eq!(eval("sin^2 + cos^2"), "Function(sin ^ 2 + cos ^ 2)");
+        assert_eq!(eval("(sin^2 + cos^2)(0.7)"), "Number(1)");
+        assert_eq!(eval("(sin + 1)(0)"), "Number(1)");
+    }
+
+    #[test]
+    fn lifted_functions_simplify_and_differentiate() {
+        assert_eq!(eval("simplify(0 * sin + 1 * cos)"), "Function(cos(x))");
+        assert_eq!(eval("derivative(sin)"), "Function(cos(x))");
+        assert_eq!(eval("derivative(x -> x^2)"), "function call failure: invalid arguments [Function(<anonymous>(x))] to derivative(f)");
+    }
+}
@@ -1,50 +1,69 @@
-use mexpr::expr::*;
+use mexpr::{decimal::*, expr::*, stdlib};
 //use mexpr::parse::*;
-use std::{io, rc::Rc};
+use std::{env, io::{self, Read}, ops::RangeInclusive, thread};
+
+/// The numbers of significant digits accepted by `--precision`.
+const PRECISION: RangeInclusive<usize> = 1..=1_000;
+/// The stack size of the evaluating thread, enough for the deepest recursion allowed even in debug builds.
+const STACK_SIZE: usize = 256 << 20;
+
+fn mode() -> Result<Mode, String> {
+    let mut precision = None;
+    let mut rounding = Rounding::HalfEven;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--precision" => match args.next().and_then(|arg| arg.parse().ok()) {
+                Some(digits) if PRECISION.contains(&digits) => precision = Some(digits),
+                _ => return Err(format!("--precision expects a number of significant digits from {} to {}", PRECISION.start(), PRECISION.end())),
+            },
+            "--rounding" => rounding = match args.next().as_deref() {
+                Some("half-even") => Rounding::HalfEven,
+                Some("half-up") => Rounding::HalfUp,
+                Some("half-down") => Rounding::HalfDown,
+                Some("up") => Rounding::Up,
+                Some("down") => Rounding::Down,
+                Some("ceiling") => Rounding::Ceiling,
+                Some("floor") => Rounding::Floor,
+                _ => return Err("--rounding expects one of half-even, half-up, half-down, up, down, ceiling or floor".to_owned()),
+            },
+            _ => return Err(format!("unknown argument \"{}\"", arg)),
+        }
+    }
+    Ok(match precision {
+        Some(precision) => Mode::Decimal(Context::new(precision, rounding)),
+        None => Mode::Float,
+    })
+}
 
 fn main() {
+    let mode = match mode() {
+        Ok(mode) => mode,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        },
+    };
     let mut string = String::new();
-    io::stdin().read_line(&mut string).expect("Failed to read line.");
-    match Expression::try_from(string.as_str()) {
+    io::stdin().read_to_string(&mut string).expect("Failed to read input.");
+    let evaluator = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        set_stack_limit(STACK_SIZE / 2);
+        run(&string, mode)
+    }).expect("Failed to start evaluation.");
+    evaluator.join().expect("Evaluation panicked.");
+    /*match Parser::from(string.as_str()).parse() {
+        Ok(tree) => {
+            println!("{:?}", tree)
+        },
+        Err(err) => println!("{}", err),
+    }*/
+}
+
+fn run(string: &str, mode: Mode) {
+    match Expression::try_from(string) {
         Ok(mut expr) => {
-            expr.table.insert("π".to_owned(), Value::Number(std::f64::consts::PI));
-            expr.table.insert("τ".to_owned(), Value::Number(2.0 * std::f64::consts::PI));
-            expr.table.insert("sin".to_owned(), Value::Function(Rc::new(|args| {
-                match args.as_slice() {
-                    [Value::Number(arg)] => Ok(Value::Number(arg.sin())),
-                    _ => Err(Box::new(InvalidArguments(args))),
-                }
-            })));
-            expr.table.insert("cos".to_owned(), Value::Function(Rc::new(|args| {
-                match args.as_slice() {
-                    [Value::Number(arg)] => Ok(Value::Number(arg.cos())),
-                    _ => Err(Box::new(InvalidArguments(args))),
-                }
-            })));
-            expr.table.insert("tan".to_owned(), Value::Function(Rc::new(|args| {
-                match args.as_slice() {
-                    [Value::Number(arg)] => Ok(Value::Number(arg.tan())),
-                    _ => Err(Box::new(InvalidArguments(args))),
-                }
-            })));
-            expr.table.insert("asin".to_owned(), Value::Function(Rc::new(|args| {
-                match args.as_slice() {
-                    [Value::Number(arg)] => Ok(Value::Number(arg.asin())),
-                    _ => Err(Box::new(InvalidArguments(args))),
-                }
-            })));
-            expr.table.insert("acos".to_owned(), Value::Function(Rc::new(|args| {
-                match args.as_slice() {
-                    [Value::Number(arg)] => Ok(Value::Number(arg.acos())),
-                    _ => Err(Box::new(InvalidArguments(args))),
-                }
-            })));
-            expr.table.insert("atan".to_owned(), Value::Function(Rc::new(|args| {
-                match args.as_slice() {
-                    [Value::Number(arg)] => Ok(Value::Number(arg.atan())),
-                    _ => Err(Box::new(InvalidArguments(args))),
-                }
-            })));
+            expr.mode = mode;
+            stdlib::install(&mut expr.table, mode);
             match expr.eval() {
                 Ok(value) => println!("{:?}", value),
                 Err(error) => println!("{}", error),
@@ -52,10 +71,4 @@ fn main() {
         },
         Err(err) => println!("ParseError: {}", err),
     };
-    /*match Parser::from(string.as_str()).parse() {
-        Ok(tree) => {
-            println!("{:?}", tree)
-        },
-        Err(err) => println!("{}", err),
-    }*/
 }
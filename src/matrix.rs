@@ -0,0 +1,238 @@
+use std::{error::Error, fmt::{Debug, Display}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixError {
+    ShapeMismatch((usize, usize), (usize, usize)),
+    NotSquare((usize, usize)),
+    Singular,
+}
+impl Display for MatrixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ShapeMismatch((rows1, cols1), (rows2, cols2)) => write!(f, "shape mismatch between {}x{} and {}x{}", rows1, cols1, rows2, cols2),
+            Self::NotSquare((rows, cols)) => write!(f, "expected a square matrix but found {}x{}", rows, cols),
+            Self::Singular => write!(f, "singular matrix"),
+        }
+    }
+}
+impl Error for MatrixError {}
+
+/// A dense row-major matrix of floats with at least one row and one column.
+#[derive(Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+impl Matrix {
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Option<Self> {
+        let cols = rows.first()?.len();
+        if cols == 0 || rows.iter().any(|row| row.len() != cols) {
+            return None;
+        }
+        Some(Self {
+            rows: rows.len(),
+            cols,
+            data: rows.concat(),
+        })
+    }
+    pub fn identity(size: usize) -> Self {
+        let mut data = vec![0.0; size * size];
+        for i in 0..size {
+            data[i * size + i] = 1.0;
+        }
+        Self { rows: size, cols: size, data }
+    }
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.cols + col]
+    }
+    pub fn row(&self, row: usize) -> &[f64] {
+        &self.data[row * self.cols..(row + 1) * self.cols]
+    }
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().map(|&x| f(x)).collect(),
+        }
+    }
+    pub fn zip_with(&self, other: &Self, f: impl Fn(f64, f64) -> f64) -> Result<Self, MatrixError> {
+        if self.shape() != other.shape() {
+            return Err(MatrixError::ShapeMismatch(self.shape(), other.shape()));
+        }
+        Ok(Self {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().zip(&other.data).map(|(&x, &y)| f(x, y)).collect(),
+        })
+    }
+    pub fn transpose(&self) -> Self {
+        let mut data = Vec::with_capacity(self.data.len());
+        for col in 0..self.cols {
+            for row in 0..self.rows {
+                data.push(self.get(row, col));
+            }
+        }
+        Self { rows: self.cols, cols: self.rows, data }
+    }
+    pub fn mul(&self, other: &Self) -> Result<Self, MatrixError> {
+        if self.cols != other.rows {
+            return Err(MatrixError::ShapeMismatch(self.shape(), other.shape()));
+        }
+        let mut data = vec![0.0; self.rows * other.cols];
+        for row in 0..self.rows {
+            for k in 0..self.cols {
+                let x = self.get(row, k);
+                for col in 0..other.cols {
+                    data[row * other.cols + col] += x * other.get(k, col);
+                }
+            }
+        }
+        Ok(Self { rows: self.rows, cols: other.cols, data })
+    }
+    pub fn mul_vector(&self, vector: &[f64]) -> Result<Vec<f64>, MatrixError> {
+        if self.cols != vector.len() {
+            return Err(MatrixError::ShapeMismatch(self.shape(), (vector.len(), 1)));
+        }
+        Ok((0..self.rows).map(|row| self.row(row).iter().zip(vector).map(|(x, y)| x * y).sum()).collect())
+    }
+    /// Reduces `self` to upper triangular form with partial pivoting, applying the same row
+    /// operations to `other`, and returns the sign of the row permutation.
+    fn eliminate(&mut self, other: &mut Self) -> Result<f64, MatrixError> {
+        let size = self.rows;
+        let scale = self.data.iter().fold(0.0f64, |max, x| max.max(x.abs()));
+        let mut sign = 1.0;
+        for col in 0..size {
+            let pivot = (col..size).max_by(|&i, &j| self.get(i, col).abs().total_cmp(&self.get(j, col).abs())).unwrap();
+            if self.get(pivot, col).abs() <= scale * size as f64 * f64::EPSILON {
+                return Err(MatrixError::Singular);
+            }
+            if pivot != col {
+                self.swap_rows(pivot, col);
+                other.swap_rows(pivot, col);
+                sign = -sign;
+            }
+            for row in col + 1..size {
+                let factor = self.get(row, col) / self.get(col, col);
+                for k in col..size {
+                    self.data[row * size + k] -= factor * self.get(col, k);
+                }
+                for k in 0..other.cols {
+                    other.data[row * other.cols + k] -= factor * other.get(col, k);
+                }
+            }
+        }
+        Ok(sign)
+    }
+    fn swap_rows(&mut self, row1: usize, row2: usize) {
+        for col in 0..self.cols {
+            self.data.swap(row1 * self.cols + col, row2 * self.cols + col);
+        }
+    }
+    fn square(&self) -> Result<usize, MatrixError> {
+        match self.rows == self.cols {
+            true => Ok(self.rows),
+            false => Err(MatrixError::NotSquare(self.shape())),
+        }
+    }
+    pub fn det(&self) -> Result<f64, MatrixError> {
+        let size = self.square()?;
+        let mut upper = self.clone();
+        match upper.eliminate(&mut Self { rows: size, cols: 0, data: Vec::new() }) {
+            Ok(sign) => Ok((0..size).fold(sign, |det, i| det * upper.get(i, i))),
+            Err(MatrixError::Singular) => Ok(0.0),
+            Err(err) => Err(err),
+        }
+    }
+    /// Solves `self * x = rhs` for every column of `rhs`.
+    pub fn solve(&self, rhs: &Self) -> Result<Self, MatrixError> {
+        let size = self.square()?;
+        if rhs.rows != size {
+            return Err(MatrixError::ShapeMismatch(self.shape(), rhs.shape()));
+        }
+        let mut upper = self.clone();
+        let mut x = rhs.clone();
+        upper.eliminate(&mut x)?;
+        for row in (0..size).rev() {
+            for col in 0..x.cols {
+                let sum = (row + 1..size).fold(x.get(row, col), |sum, k| sum - upper.get(row, k) * x.get(k, col));
+                x.data[row * x.cols + col] = sum / upper.get(row, row);
+            }
+        }
+        Ok(x)
+    }
+    pub fn solve_vector(&self, rhs: &[f64]) -> Result<Vec<f64>, MatrixError> {
+        let column = Self { rows: rhs.len(), cols: 1, data: rhs.to_vec() };
+        Ok(self.solve(&column)?.data)
+    }
+    pub fn inverse(&self) -> Result<Self, MatrixError> {
+        self.solve(&Self::identity(self.square()?))
+    }
+}
+impl Display for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for row in 0..self.rows {
+            if row > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "[")?;
+            for (col, x) in self.row(row).iter().enumerate() {
+                if col > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", x)?;
+            }
+            write!(f, "]")?;
+        }
+        write!(f, "]")
+    }
+}
+impl Debug for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[f64]]) -> Matrix {
+        Matrix::from_rows(rows.iter().map(|row| row.to_vec()).collect()).unwrap()
+    }
+
+    #[test]
+    fn shapes_must_agree() {
+        assert!(Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0]]).is_none());
+        assert!(Matrix::from_rows(vec![vec![]]).is_none());
+        let square = matrix(&[&[1.0, 2.0], &[3.0, 4.0]]);
+        let wide = matrix(&[&[1.0, 2.0, 3.0]]);
+        assert_eq!(square.mul(&wide), Err(MatrixError::ShapeMismatch((2, 2), (1, 3))));
+        assert_eq!(square.zip_with(&wide, |x, y| x + y), Err(MatrixError::ShapeMismatch((2, 2), (1, 3))));
+        assert_eq!(square.mul_vector(&[1.0]), Err(MatrixError::ShapeMismatch((2, 2), (1, 1))));
+        assert_eq!(wide.det(), Err(MatrixError::NotSquare((1, 3))));
+        assert_eq!(wide.transpose().shape(), (3, 1));
+        assert_eq!(wide.mul(&wide.transpose()).map(|product| product.to_string()), Ok("[[14]]".to_owned()));
+    }
+
+    #[test]
+    fn elimination_solves_and_detects_singular_matrices() {
+        let square = matrix(&[&[2.0, 1.0], &[1.0, 3.0]]);
+        assert_eq!(square.det(), Ok(5.0));
+        assert_eq!(square.solve_vector(&[3.0, 4.0]), Ok(vec![1.0, 1.0]));
+        assert_eq!(matrix(&[&[2.0, 0.0], &[0.0, 4.0]]).inverse().unwrap().to_string(), "[[0.5, 0], [0, 0.25]]");
+        let singular = matrix(&[&[1.0, 2.0], &[2.0, 4.0]]);
+        assert_eq!(singular.det(), Ok(0.0));
+        assert_eq!(singular.inverse(), Err(MatrixError::Singular));
+    }
+}
@@ -1,14 +1,53 @@
-use crate::token::*;
+use crate::{decimal::Decimal, token::*};
 use std::{error::Error, fmt::Display, iter::Peekable};
 
 /* 
  * mexpr grammar
  * --------------------------------------------------------
- * atom = (number | identifier | "+" | "-" | "*" | "/" | "%" | "^" | "(" expr ")") ("(" expr? ("," expr)* ")")*
- * primary = atom ("^" factor)?
- * factor = (("+" | "-") factor) | primary
+ * operator = "+" | "-" | "*" | "/" | "%" | "^" | "==" | "!=" | "<" | "<=" | ">" | ">=" | "!" | "∘"
+ * binary = "+" | "-" | "*" | "/" | "%" | "^" | "==" | "!=" | "<" | "<=" | ">" | ">=" | "∘"
+ * conditional = "if" expr "then" expr "else" expr
+ * binding = identifier "=" expr
+ * let = "let" binding ("," binding)* "in" expr
+ * list = "[" (expr ("," expr)*)? "]"
+ * tuple = "(" (expr "," (expr ("," expr)* ","?)?)? ")"
+ * section = "(" expr binary ")" | "(" binary expr ")"     (a leading "+" or "-" is a sign instead)
+ * record = "{" (identifier ":" expr ("," identifier ":" expr)*)? "}"
+ * branch = (expr | "else") ":" expr
+ * piecewise = "piecewise" "{" branch ((";" | newline) branch)* ";"? "}"     ("else" may only be the last branch)
+ * call = "(" ((binding | expr) ("," (binding | expr))*)? ")"
+ * index = "[" expr "]"
+ * slice = "[" expr? ":" expr? "]"
+ * field = "." identifier
+ * atom = (number | string | identifier | operator | conditional | let | list | tuple | section | record | piecewise | "(" expr ")") (call | index | slice | field)*
+ * composition = atom ("∘" composition)?
+ * primary = composition ("^" factor)?
+ * factor = (("+" | "-" | "!") factor) | primary
  * term = (factor (("*" | "/" | "%") factor)*)
- * expr = (term (("+" | "-") term)*)
+ * arithmetic = (term (("+" | "-") term)*)
+ * comparison = arithmetic (("==" | "!=" | "<" | "<=" | ">" | ">=") arithmetic | ".." arithmetic (":" arithmetic)?)?
+ * conjunction = (comparison ("&&" comparison)*)
+ * disjunction = (conjunction ("||" conjunction)*)
+ * pipeline = (disjunction ("|>" disjunction)*)
+ * parameters = identifier | "(" (identifier ("," identifier)*)? ")"
+ * expr = ((parameters "->" expr) | pipeline) ("where" binding ("," binding)*)?
+ * definition = identifier "(" (identifier ("," identifier)*)? ")" "=" expr
+ * pattern = identifier | "-"? number | string
+ * clause = identifier "(" pattern ("," pattern)* ")" "=" expr     (consecutive clauses of one name form a single function, tried in order)
+ * rec = "rec" "{" (definition | clause) ((";" | newline) (definition | clause))* ";"? "}"
+ * assignment = identifier "=" expr
+ * destructuring = "(" identifier ("," identifier)* ")" "=" expr
+ * use = "use" identifier ("." identifier)*
+ * import = "import" string
+ * statement = definition | clause | rec | assignment | destructuring | use | import | expr
+ * program = (";" | newline)* statement ((";" | newline)+ statement)* (";" | newline)*
+ *
+ * A newline ends a statement, a piecewise branch or a rec definition unless it is inside parentheses
+ * or brackets, so a binary operator may only start a line there.
+ *
+ * A statement sees the names defined before it as they were then, and never those defined after it,
+ * so `f(x) = x * k; k = 2` leaves `k` undefined in `f`. A function sees itself, and the functions of
+ * a rec group see each other, so they may be mutually recursive.
  * --------------------------------------------------------
  */
 
@@ -16,10 +55,20 @@ use std::{error::Error, fmt::Display, iter::Peekable};
 pub struct ParseError<'a> {
     pub expected: &'static [TokenKind],
     pub found: Token<'a>,
+    /// Why a well-formed statement was rejected, which then explains the error instead of the token.
+    pub reason: Option<Reason>,
 }
 impl<'a> Display for ParseError<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.expected.len() > 0 {
+        let name = self.found.lexeme;
+        match self.reason {
+            Some(Reason::SplitClauses) => return write!(f, "clauses of {} must be adjacent", name),
+            Some(Reason::UnreachableClause) => return write!(f, "clause of {} follows one matching any arguments, so it is never tried", name),
+            Some(Reason::NotAFunction) => return write!(f, "rec groups may only define functions"),
+            Some(Reason::Redefined) => return write!(f, "{} is defined twice in a rec group", name),
+            None => (),
+        }
+        if !self.expected.is_empty() {
             let mut iter = self.expected.iter();
             write!(f, "expected a token of type ")?;
             write!(f, "{}", iter.next().unwrap())?;
@@ -33,11 +82,339 @@ impl<'a> Display for ParseError<'a> {
 }
 impl<'a> Error for ParseError<'a> {}
 
+/// A statement that parses but cannot be accepted where it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Clauses of a function separated by another statement.
+    SplitClauses,
+    /// A clause after one whose patterns are all names.
+    UnreachableClause,
+    /// A member of a `rec` group that is not a function definition.
+    NotAFunction,
+    /// A function defined apart twice in one `rec` group.
+    Redefined,
+}
+
+/// Where a piece of the source is, as byte offsets and the line and column it starts at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A parameter of a definition clause, which either binds the argument or only matches an equal one.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Name(String),
+    Literal(ParseTree),
+}
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Literal(literal) => write!(f, "{}", literal),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ParseTree {
-    Number(f64),
+    /// A number literal, exactly as written and as the nearest float, so neither mode converts it
+    /// on every evaluation.
+    Number(Decimal, f64),
+    String(String),
     Identifier(String),
     FunctionCall(Box<ParseTree>, Vec<ParseTree>),
+    Pipe(Box<ParseTree>, Box<ParseTree>),
+    Range(Box<ParseTree>, Box<ParseTree>, Option<Box<ParseTree>>),
+    Binding(String, Box<ParseTree>),
+    And(Box<ParseTree>, Box<ParseTree>),
+    Or(Box<ParseTree>, Box<ParseTree>),
+    Conditional(Box<ParseTree>, Box<ParseTree>, Box<ParseTree>),
+    Piecewise(Vec<(ParseTree, ParseTree)>, Option<Box<ParseTree>>, Span),
+    Lambda(Vec<String>, Box<ParseTree>),
+    Definition(String, Vec<String>, Box<ParseTree>),
+    Clauses(String, Vec<(Vec<Pattern>, ParseTree)>),
+    /// Functions defined together in a `rec` group, which see each other.
+    Recursive(Vec<ParseTree>),
+    Assignment(String, Box<ParseTree>),
+    Let(Vec<(String, ParseTree)>, Box<ParseTree>),
+    List(Vec<ParseTree>),
+    Tuple(Vec<ParseTree>),
+    Record(Vec<(String, ParseTree)>),
+    Field(Box<ParseTree>, String),
+    Destructuring(Vec<String>, Box<ParseTree>),
+    Use(Box<ParseTree>),
+    Import(String),
+    Index(Box<ParseTree>, Box<ParseTree>),
+    Slice(Box<ParseTree>, Option<Box<ParseTree>>, Option<Box<ParseTree>>),
+    LeftSection(Box<ParseTree>, String),
+    RightSection(String, Box<ParseTree>),
+}
+impl ParseTree {
+    fn number(num: Decimal) -> Self {
+        let float = num.to_f64();
+        Self::Number(num, float)
+    }
+    /// The names referred to anywhere in the tree, including operators used in sections.
+    pub fn identifiers(&self) -> Vec<&str> {
+        let children: Vec<&ParseTree> = match self {
+            Self::Identifier(ident) => return vec![ident.as_str()],
+            Self::LeftSection(operand, operator) | Self::RightSection(operator, operand) => {
+                let mut identifiers = operand.identifiers();
+                identifiers.push(operator.as_str());
+                return identifiers;
+            },
+            Self::Number(_, _) | Self::String(_) | Self::Import(_) => Vec::new(),
+            Self::FunctionCall(func, args) => std::iter::once(&**func).chain(args).collect(),
+            Self::Pipe(lhs, rhs) | Self::And(lhs, rhs) | Self::Or(lhs, rhs) | Self::Index(lhs, rhs) => vec![lhs, rhs],
+            Self::Range(start, end, step) => [Some(start), Some(end), step.as_ref()].into_iter().flatten().map(|tree| &**tree).collect(),
+            Self::Slice(list, start, end) => [Some(list), start.as_ref(), end.as_ref()].into_iter().flatten().map(|tree| &**tree).collect(),
+            Self::Binding(_, value) | Self::Lambda(_, value) | Self::Definition(_, _, value) | Self::Assignment(_, value) | Self::Destructuring(_, value) | Self::Use(value) | Self::Field(value, _) => vec![value],
+            Self::Conditional(condition, consequent, alternative) => vec![condition, consequent, alternative],
+            Self::Piecewise(branches, otherwise, _) => branches.iter().flat_map(|(condition, value)| [condition, value]).chain(otherwise.as_deref()).collect(),
+            Self::Clauses(_, clauses) => clauses.iter().map(|(_, body)| body).collect(),
+            Self::Recursive(members) => members.iter().collect(),
+            Self::Let(bindings, body) => bindings.iter().map(|(_, value)| value).chain(std::iter::once(&**body)).collect(),
+            Self::List(items) | Self::Tuple(items) => items.iter().collect(),
+            Self::Record(fields) => fields.iter().map(|(_, value)| value).collect(),
+        };
+        children.into_iter().flat_map(ParseTree::identifiers).collect()
+    }
+    /// The name and arity of a function definition, and whether any of its parameters are literal patterns.
+    fn signature(&self) -> Option<(&str, usize, bool)> {
+        match self {
+            Self::Definition(name, params, _) => Some((name, params.len(), false)),
+            Self::Clauses(name, clauses) => Some((name, clauses[0].0.len(), true)),
+            _ => None,
+        }
+    }
+    /// The clauses of a function definition in order.
+    fn into_clauses(self) -> Vec<(Vec<Pattern>, ParseTree)> {
+        match self {
+            Self::Definition(_, params, body) => vec![(params.into_iter().map(Pattern::Name).collect(), *body)],
+            Self::Clauses(_, clauses) => clauses,
+            _ => Vec::new(),
+        }
+    }
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::FunctionCall(func, args) => match (&**func, args.len()) {
+                (Self::Identifier(operator), 2) => match operator.as_str() {
+                    "==" | "!=" | "<" | "<=" | ">" | ">=" => 3,
+                    "+" | "-" => 4,
+                    "*" | "/" | "%" => 5,
+                    "^" => 7,
+                    "∘" => 8,
+                    _ => 9,
+                },
+                (Self::Identifier(operator), 1) if matches!(operator.as_str(), "+" | "-" | "!") => 6,
+                _ => 9,
+            },
+            Self::Range(_, _, _) => 3,
+            Self::Or(_, _) => 1,
+            Self::And(_, _) => 2,
+            Self::Pipe(_, _) | Self::Binding(_, _) | Self::Conditional(_, _, _) | Self::Lambda(_, _) | Self::Definition(_, _, _) | Self::Clauses(_, _) | Self::Recursive(_) | Self::Assignment(_, _) | Self::Destructuring(_, _) | Self::Use(_) | Self::Import(_) | Self::Let(_, _) => 0,
+            Self::Number(_, _) | Self::String(_) | Self::Identifier(_) | Self::List(_) | Self::Tuple(_) | Self::Record(_) | Self::Field(_, _) | Self::Index(_, _) | Self::Slice(_, _, _) | Self::LeftSection(_, _) | Self::RightSection(_, _) | Self::Piecewise(_, _, _) => 9,
+        }
+    }
+    fn fmt_with(&self, f: &mut std::fmt::Formatter<'_>, precedence: u8) -> std::fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+impl Display for ParseTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(num, _) => write!(f, "{}", num),
+            Self::String(string) => write!(f, "{:?}", string),
+            Self::Identifier(ident) => write!(f, "{}", ident),
+            Self::FunctionCall(func, args) => match (self.precedence(), &**func, args.as_slice()) {
+                (8, Self::Identifier(operator), [lhs, rhs]) => {
+                    lhs.fmt_with(f, 9)?;
+                    write!(f, " {} ", operator)?;
+                    rhs.fmt_with(f, 8)
+                },
+                (7, Self::Identifier(operator), [lhs, rhs]) => {
+                    lhs.fmt_with(f, 8)?;
+                    write!(f, " {} ", operator)?;
+                    rhs.fmt_with(f, 6)
+                },
+                (precedence @ 3..=5, Self::Identifier(operator), [lhs, rhs]) => {
+                    lhs.fmt_with(f, precedence)?;
+                    write!(f, " {} ", operator)?;
+                    rhs.fmt_with(f, if precedence == 3 { 4 } else { precedence + 1 })
+                },
+                (6, Self::Identifier(operator), [arg]) => {
+                    write!(f, "{}", operator)?;
+                    arg.fmt_with(f, 6)
+                },
+                _ => {
+                    func.fmt_with(f, 9)?;
+                    write!(f, "(")?;
+                    let mut iter = args.iter();
+                    if let Some(arg) = iter.next() {
+                        write!(f, "{}", arg)?;
+                    }
+                    for arg in iter {
+                        write!(f, ", {}", arg)?;
+                    }
+                    write!(f, ")")
+                },
+            },
+            Self::Pipe(lhs, rhs) => {
+                match &**lhs {
+                    Self::Pipe(_, _) => write!(f, "{}", lhs)?,
+                    lhs => lhs.fmt_with(f, 1)?,
+                }
+                write!(f, " |> ")?;
+                rhs.fmt_with(f, 1)
+            },
+            Self::Range(start, end, step) => {
+                start.fmt_with(f, 4)?;
+                write!(f, "..")?;
+                end.fmt_with(f, 4)?;
+                match step {
+                    Some(step) => {
+                        write!(f, ":")?;
+                        step.fmt_with(f, 4)
+                    },
+                    None => Ok(()),
+                }
+            },
+            Self::Binding(name, value) => write!(f, "{} = {}", name, value),
+            Self::And(lhs, rhs) => {
+                lhs.fmt_with(f, 2)?;
+                write!(f, " && ")?;
+                rhs.fmt_with(f, 3)
+            },
+            Self::Or(lhs, rhs) => {
+                lhs.fmt_with(f, 1)?;
+                write!(f, " || ")?;
+                rhs.fmt_with(f, 2)
+            },
+            Self::Conditional(condition, consequent, alternative) => write!(f, "if {} then {} else {}", condition, consequent, alternative),
+            Self::Piecewise(branches, otherwise, _) => {
+                write!(f, "piecewise {{ ")?;
+                let mut iter = branches.iter();
+                if let Some((condition, value)) = iter.next() {
+                    write!(f, "{}: {}", condition, value)?;
+                }
+                for (condition, value) in iter {
+                    write!(f, "; {}: {}", condition, value)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    if !branches.is_empty() {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "else: {}", otherwise)?;
+                }
+                write!(f, " }}")
+            },
+            Self::Lambda(params, body) => match params.as_slice() {
+                [param] => write!(f, "{} -> {}", param, body),
+                _ => write!(f, "({}) -> {}", params.join(", "), body),
+            },
+            Self::Definition(name, params, body) => write!(f, "{}({}) = {}", name, params.join(", "), body),
+            Self::Clauses(name, clauses) => {
+                for (i, (patterns, body)) in clauses.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    let patterns = patterns.iter().map(Pattern::to_string).collect::<Vec<_>>();
+                    write!(f, "{}({}) = {}", name, patterns.join(", "), body)?;
+                }
+                Ok(())
+            },
+            Self::Recursive(members) => {
+                let members = members.iter().map(ParseTree::to_string).collect::<Vec<_>>();
+                write!(f, "rec {{ {} }}", members.join("; "))
+            },
+            Self::Assignment(name, value) => write!(f, "{} = {}", name, value),
+            Self::Destructuring(names, value) => write!(f, "({}) = {}", names.join(", "), value),
+            Self::Use(module) => write!(f, "use {}", module),
+            Self::Import(path) => write!(f, "import {:?}", path),
+            Self::Let(bindings, body) => {
+                write!(f, "let ")?;
+                let mut iter = bindings.iter();
+                if let Some((name, value)) = iter.next() {
+                    write!(f, "{} = {}", name, value)?;
+                }
+                for (name, value) in iter {
+                    write!(f, ", {} = {}", name, value)?;
+                }
+                write!(f, " in {}", body)
+            },
+            Self::List(items) => {
+                write!(f, "[")?;
+                let mut iter = items.iter();
+                if let Some(item) = iter.next() {
+                    write!(f, "{}", item)?;
+                }
+                for item in iter {
+                    write!(f, ", {}", item)?;
+                }
+                write!(f, "]")
+            },
+            Self::Tuple(items) => match items.as_slice() {
+                [item] => write!(f, "({},)", item),
+                _ => {
+                    write!(f, "(")?;
+                    let mut iter = items.iter();
+                    if let Some(item) = iter.next() {
+                        write!(f, "{}", item)?;
+                    }
+                    for item in iter {
+                        write!(f, ", {}", item)?;
+                    }
+                    write!(f, ")")
+                },
+            },
+            Self::Record(fields) => {
+                write!(f, "{{")?;
+                let mut iter = fields.iter();
+                if let Some((name, value)) = iter.next() {
+                    write!(f, "{}: {}", name, value)?;
+                }
+                for (name, value) in iter {
+                    write!(f, ", {}: {}", name, value)?;
+                }
+                write!(f, "}}")
+            },
+            Self::Field(record, name) => {
+                record.fmt_with(f, 9)?;
+                write!(f, ".{}", name)
+            },
+            Self::Index(list, index) => {
+                list.fmt_with(f, 9)?;
+                write!(f, "[{}]", index)
+            },
+            Self::Slice(list, start, end) => {
+                list.fmt_with(f, 9)?;
+                write!(f, "[")?;
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, ":")?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                write!(f, "]")
+            },
+            Self::LeftSection(operand, operator) => write!(f, "({} {})", operand, operator),
+            Self::RightSection(operator, operand) => write!(f, "({} {})", operator, operand),
+        }
+    }
 }
 impl<'a> TryFrom<&'a str> for ParseTree {
     type Error = ParseError<'a>;
@@ -47,15 +424,355 @@ impl<'a> TryFrom<&'a str> for ParseTree {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub statements: Vec<ParseTree>,
+}
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut iter = self.statements.iter();
+        if let Some(statement) = iter.next() {
+            write!(f, "{}", statement)?;
+        }
+        for statement in iter {
+            write!(f, "; {}", statement)?;
+        }
+        Ok(())
+    }
+}
+impl<'a> TryFrom<&'a str> for Program {
+    type Error = ParseError<'a>;
+
+    fn try_from(string: &'a str) -> Result<Self, Self::Error> {
+        Parser::from(string).parse_program()
+    }
+}
+
+/// Resolves the escapes of a string literal, including its quotes.
+fn unescape(lexeme: &str) -> Option<String> {
+    let mut string = String::new();
+    let mut iter = lexeme[1..lexeme.len() - 1].chars();
+    while let Some(ch) = iter.next() {
+        string.push(match ch {
+            '\\' => match iter.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                '\\' => '\\',
+                '"' => '"',
+                '\'' => '\'',
+                'u' => {
+                    if iter.next()? != '{' {
+                        return None;
+                    }
+                    let mut code = String::new();
+                    loop {
+                        match iter.next()? {
+                            '}' => break,
+                            ch => code.push(ch),
+                        }
+                    }
+                    char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+                },
+                _ => return None,
+            },
+            ch => ch,
+        });
+    }
+    Some(string)
+}
+
+/// The name of a binary operator token.
+fn binary(kind: TokenKind) -> Option<&'static str> {
+    match kind {
+        TokenKind::Plus => Some("+"),
+        TokenKind::Minus => Some("-"),
+        TokenKind::Star => Some("*"),
+        TokenKind::Slash => Some("/"),
+        TokenKind::Percent => Some("%"),
+        TokenKind::Caret => Some("^"),
+        TokenKind::EqualEqual => Some("=="),
+        TokenKind::ExclamationEqual => Some("!="),
+        TokenKind::Less => Some("<"),
+        TokenKind::LessEqual => Some("<="),
+        TokenKind::Greater => Some(">"),
+        TokenKind::GreaterEqual => Some(">="),
+        TokenKind::Ring => Some("∘"),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Parser<'a> {
+    source: &'a str,
     iter: Peekable<Tokens<'a>>,
+    newline: bool,
+    /// The parentheses, brackets and braces that are open.
+    groups: Vec<TokenKind>,
 }
 impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Token<'a> {
+        while let TokenKind::Newline = self.iter.peek().unwrap().kind {
+            self.iter.next();
+            self.newline |= matches!(self.groups.last(), None | Some(TokenKind::LeftBrace));
+        }
+        *self.iter.peek().unwrap()
+    }
+    fn next(&mut self) -> Token<'a> {
+        let token = self.peek();
+        self.iter.next();
+        self.newline = false;
+        match token.kind {
+            TokenKind::LeftParenthesis | TokenKind::LeftBracket | TokenKind::LeftBrace => self.groups.push(token.kind),
+            TokenKind::RightParenthesis | TokenKind::RightBracket | TokenKind::RightBrace => {
+                self.groups.pop();
+            },
+            _ => (),
+        }
+        token
+    }
+    fn expect(&mut self, kind: TokenKind, expected: &'static [TokenKind]) -> Result<Token<'a>, ParseError<'a>> {
+        let token = self.next();
+        if token.kind == kind {
+            Ok(token)
+        } else {
+            Err(ParseError {
+                expected,
+                found: token,
+                reason: None,
+            })
+        }
+    }
+    /// Whether the next tokens are a binary operator and ")", which end a left section such as `(2 *)`.
+    fn closes_section(&mut self) -> bool {
+        let mut parser = self.clone();
+        binary(parser.next().kind).is_some() && parser.peek().kind == TokenKind::RightParenthesis
+    }
+    fn conditional(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let condition = self.expr()?;
+        self.expect(TokenKind::Then, &[TokenKind::Then])?;
+        let consequent = self.expr()?;
+        self.expect(TokenKind::Else, &[TokenKind::Else])?;
+        let alternative = self.expr()?;
+        Ok(ParseTree::Conditional(Box::new(condition), Box::new(consequent), Box::new(alternative)))
+    }
+    /// The span from the start of the first token to the end of the last.
+    fn span(&self, first: Token<'a>, last: Token<'a>) -> Span {
+        let offset = |token: Token<'a>| token.lexeme.as_ptr() as usize - self.source.as_ptr() as usize;
+        let start = offset(first);
+        let before = &self.source[..start];
+        Span {
+            start,
+            end: offset(last) + last.lexeme.len(),
+            line: before.matches('\n').count() + 1,
+            column: before.chars().rev().take_while(|&ch| ch != '\n').count() + 1,
+        }
+    }
+    fn piecewise(&mut self, keyword: Token<'a>) -> Result<ParseTree, ParseError<'a>> {
+        self.expect(TokenKind::LeftBrace, &[TokenKind::LeftBrace])?;
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            if let TokenKind::Else = self.peek().kind {
+                self.next();
+                self.expect(TokenKind::Colon, &[TokenKind::Colon])?;
+                otherwise = Some(Box::new(self.expr()?));
+                if let TokenKind::Semicolon = self.peek().kind {
+                    self.next();
+                }
+                break;
+            }
+            let condition = self.expr()?;
+            self.expect(TokenKind::Colon, &[TokenKind::Colon])?;
+            branches.push((condition, self.expr()?));
+            match self.peek().kind {
+                TokenKind::Semicolon => {
+                    self.next();
+                },
+                TokenKind::RightBrace => break,
+                _ if self.newline => (),
+                _ => break,
+            }
+            if let TokenKind::RightBrace = self.peek().kind {
+                break;
+            }
+        }
+        let last = self.expect(TokenKind::RightBrace, &[TokenKind::Semicolon, TokenKind::RightBrace])?;
+        Ok(ParseTree::Piecewise(branches, otherwise, self.span(keyword, last)))
+    }
+    fn group(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        self.expect(TokenKind::LeftBrace, &[TokenKind::LeftBrace])?;
+        let mut members = Vec::new();
+        loop {
+            let first = self.peek();
+            let member = self.statement()?;
+            let Some((name, _, _)) = member.signature() else {
+                return Err(ParseError {
+                    expected: &[],
+                    found: first,
+                    reason: Some(Reason::NotAFunction),
+                });
+            };
+            let name = name.to_owned();
+            merge(&mut members, member, first)?;
+            // Each function of a group is defined once, since the others see it under its name.
+            if members.iter().filter(|member| member.signature().is_some_and(|other| other.0 == name)).count() > 1 {
+                return Err(ParseError {
+                    expected: &[],
+                    found: first,
+                    reason: Some(Reason::Redefined),
+                });
+            }
+            match self.peek().kind {
+                TokenKind::Semicolon => {
+                    self.next();
+                },
+                TokenKind::RightBrace => break,
+                _ if self.newline => (),
+                _ => break,
+            }
+            if let TokenKind::RightBrace = self.peek().kind {
+                break;
+            }
+        }
+        self.expect(TokenKind::RightBrace, &[TokenKind::Semicolon, TokenKind::RightBrace])?;
+        Ok(ParseTree::Recursive(members))
+    }
+    fn binding(&mut self) -> Result<(String, ParseTree), ParseError<'a>> {
+        let name = self.expect(TokenKind::Identifier, &[TokenKind::Identifier])?.lexeme.to_owned();
+        self.expect(TokenKind::Equal, &[TokenKind::Equal])?;
+        Ok((name, self.expr()?))
+    }
+    /// A call argument, which may bind a name for a form such as `sum(k = 1..n, k^2)`.
+    fn argument(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut parser = self.clone();
+        if let (TokenKind::Identifier, TokenKind::Equal) = (parser.next().kind, parser.next().kind) {
+            let (name, value) = self.binding()?;
+            return Ok(ParseTree::Binding(name, Box::new(value)));
+        }
+        self.expr()
+    }
+    fn bindings(&mut self) -> Result<Vec<(String, ParseTree)>, ParseError<'a>> {
+        let mut bindings = vec![self.binding()?];
+        while let TokenKind::Comma = self.peek().kind {
+            let mut parser = self.clone();
+            parser.next();
+            if let (TokenKind::Identifier, TokenKind::Equal) = (parser.next().kind, parser.next().kind) {
+                self.next();
+                bindings.push(self.binding()?);
+            } else {
+                break;
+            }
+        }
+        Ok(bindings)
+    }
+    fn let_in(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut bindings = vec![self.binding()?];
+        while let TokenKind::Comma = self.peek().kind {
+            self.next();
+            bindings.push(self.binding()?);
+        }
+        self.expect(TokenKind::In, &[TokenKind::Comma, TokenKind::In])?;
+        Ok(ParseTree::Let(bindings, Box::new(self.expr()?)))
+    }
+    fn list(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut items = Vec::new();
+        if self.peek().kind != TokenKind::RightBracket {
+            items.push(self.expr()?);
+            while let TokenKind::Comma = self.peek().kind {
+                self.next();
+                items.push(self.expr()?);
+            }
+        }
+        self.expect(TokenKind::RightBracket, &[TokenKind::Comma, TokenKind::RightBracket])?;
+        Ok(ParseTree::List(items))
+    }
+    fn tuple(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        if let TokenKind::RightParenthesis = self.peek().kind {
+            self.next();
+            return Ok(ParseTree::Tuple(Vec::new()));
+        }
+        if let Some(operator) = binary(self.peek().kind).filter(|operator| !matches!(*operator, "+" | "-")) {
+            let mut parser = self.clone();
+            parser.next();
+            if !matches!(parser.peek().kind, TokenKind::RightParenthesis | TokenKind::Comma | TokenKind::LeftParenthesis) {
+                let operand = parser.expr()?;
+                parser.expect(TokenKind::RightParenthesis, &[TokenKind::RightParenthesis])?;
+                *self = parser;
+                return Ok(ParseTree::RightSection(operator.to_owned(), Box::new(operand)));
+            }
+        }
+        let item = self.expr()?;
+        if self.closes_section() {
+            let operator = binary(self.next().kind).unwrap_or_default();
+            self.next();
+            return Ok(ParseTree::LeftSection(Box::new(item), operator.to_owned()));
+        }
+        if let TokenKind::RightParenthesis = self.peek().kind {
+            self.next();
+            return Ok(item);
+        }
+        self.expect(TokenKind::Comma, &[TokenKind::Comma, TokenKind::RightParenthesis])?;
+        let mut items = vec![item];
+        while self.peek().kind != TokenKind::RightParenthesis {
+            items.push(self.expr()?);
+            if self.peek().kind != TokenKind::Comma {
+                break;
+            }
+            self.next();
+        }
+        self.expect(TokenKind::RightParenthesis, &[TokenKind::Comma, TokenKind::RightParenthesis])?;
+        Ok(ParseTree::Tuple(items))
+    }
+    fn record(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut fields = Vec::new();
+        if self.peek().kind != TokenKind::RightBrace {
+            loop {
+                let name = self.expect(TokenKind::Identifier, &[TokenKind::Identifier])?.lexeme.to_owned();
+                self.expect(TokenKind::Colon, &[TokenKind::Colon])?;
+                fields.push((name, self.expr()?));
+                if self.peek().kind != TokenKind::Comma {
+                    break;
+                }
+                self.next();
+            }
+        }
+        self.expect(TokenKind::RightBrace, &[TokenKind::Comma, TokenKind::RightBrace])?;
+        Ok(ParseTree::Record(fields))
+    }
+    fn index(&mut self, list: ParseTree) -> Result<ParseTree, ParseError<'a>> {
+        let start = match self.peek().kind {
+            TokenKind::Colon => None,
+            _ => Some(Box::new(self.expr()?)),
+        };
+        let index = match (start, self.peek().kind) {
+            (Some(index), TokenKind::RightBracket) => ParseTree::Index(Box::new(list), index),
+            (start, _) => {
+                self.expect(TokenKind::Colon, &[TokenKind::Colon, TokenKind::RightBracket])?;
+                let end = match self.peek().kind {
+                    TokenKind::RightBracket => None,
+                    _ => Some(Box::new(self.expr()?)),
+                };
+                ParseTree::Slice(Box::new(list), start, end)
+            },
+        };
+        self.expect(TokenKind::RightBracket, &[TokenKind::RightBracket])?;
+        Ok(index)
+    }
     fn atom(&mut self) -> Result<ParseTree, ParseError<'a>> {
-        let token = self.iter.next().unwrap();
+        let token = self.next();
         let mut primary = match token.kind {
-            TokenKind::Number => Ok(ParseTree::Number(token.lexeme.parse().unwrap())),
+            TokenKind::Number => token.lexeme.parse().map(ParseTree::number).map_err(|_| ParseError {
+                expected: &[],
+                found: token,
+                reason: None,
+            }),
+            TokenKind::String => unescape(token.lexeme).map(ParseTree::String).ok_or(ParseError {
+                expected: &[],
+                found: token,
+                reason: None,
+            }),
             TokenKind::Identifier => Ok(ParseTree::Identifier(token.lexeme.to_owned())),
             TokenKind::Plus => Ok(ParseTree::Identifier("+".to_owned())),
             TokenKind::Minus => Ok(ParseTree::Identifier("-".to_owned())),
@@ -63,68 +780,97 @@ impl<'a> Parser<'a> {
             TokenKind::Slash => Ok(ParseTree::Identifier("/".to_owned())),
             TokenKind::Percent => Ok(ParseTree::Identifier("%".to_owned())),
             TokenKind::Caret => Ok(ParseTree::Identifier("^".to_owned())),
-            TokenKind::LeftParenthesis => {
-                let primary = self.expr()?;
-                let token = self.iter.next().unwrap();
-                if let TokenKind::RightParenthesis = token.kind {
-                    Ok(primary)
-                } else {
-                    Err(ParseError {
-                        expected: &[TokenKind::RightParenthesis],
-                        found: token,
-                    })
-                }
-            },
+            TokenKind::EqualEqual => Ok(ParseTree::Identifier("==".to_owned())),
+            TokenKind::ExclamationEqual => Ok(ParseTree::Identifier("!=".to_owned())),
+            TokenKind::Less => Ok(ParseTree::Identifier("<".to_owned())),
+            TokenKind::LessEqual => Ok(ParseTree::Identifier("<=".to_owned())),
+            TokenKind::Greater => Ok(ParseTree::Identifier(">".to_owned())),
+            TokenKind::GreaterEqual => Ok(ParseTree::Identifier(">=".to_owned())),
+            TokenKind::Exclamation => Ok(ParseTree::Identifier("!".to_owned())),
+            TokenKind::Ring => Ok(ParseTree::Identifier("∘".to_owned())),
+            TokenKind::If => self.conditional(),
+            TokenKind::Let => self.let_in(),
+            TokenKind::Piecewise => self.piecewise(token),
+            TokenKind::LeftBracket => self.list(),
+            TokenKind::LeftParenthesis => self.tuple(),
+            TokenKind::LeftBrace => self.record(),
             _ => {
                 Err(ParseError {
                     expected: &[
                         TokenKind::Number,
+                        TokenKind::String,
                         TokenKind::Identifier,
                         TokenKind::Plus,
                         TokenKind::Minus,
-                        TokenKind::LeftParenthesis
+                        TokenKind::Exclamation,
+                        TokenKind::If,
+                        TokenKind::Let,
+                        TokenKind::LeftParenthesis,
+                        TokenKind::LeftBracket,
+                        TokenKind::LeftBrace
                     ],
                     found: token,
+                    reason: None,
                 })
             }
         }?;
-        while let TokenKind::LeftParenthesis = self.iter.peek().unwrap().kind {
-            self.iter.next().unwrap();
+        while let (TokenKind::LeftParenthesis | TokenKind::LeftBracket | TokenKind::Dot, false) = (self.peek().kind, self.newline) {
+            match self.next().kind {
+                TokenKind::LeftBracket => {
+                    primary = self.index(primary)?;
+                    continue;
+                },
+                TokenKind::Dot => {
+                    let name = self.expect(TokenKind::Identifier, &[TokenKind::Identifier])?.lexeme.to_owned();
+                    primary = ParseTree::Field(Box::new(primary), name);
+                    continue;
+                },
+                _ => (),
+            }
             let mut args = Vec::new();
             let mut parser = self.clone();
-            if let Ok(arg) = parser.expr() {
+            if let Ok(arg) = parser.argument() {
                 *self = parser;
                 args.push(arg);
-                while let TokenKind::Comma = self.iter.peek().unwrap().kind {
-                    self.iter.next().unwrap();
-                    args.push(self.expr()?);
+                while let TokenKind::Comma = self.peek().kind {
+                    self.next();
+                    args.push(self.argument()?);
                 }
             }
-            let token = self.iter.next().unwrap();
+            let token = self.next();
             primary = if let TokenKind::RightParenthesis = token.kind {
                 Ok(ParseTree::FunctionCall(Box::new(primary), args))
             } else {
                 Err(ParseError {
                     expected: &[TokenKind::RightParenthesis],
                     found: token,
+                    reason: None,
                 })
             }?
         }
         Ok(primary)
     }
+    fn composition(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let composition = self.atom()?;
+        if let (TokenKind::Ring, false) = (self.peek().kind, self.newline || self.closes_section()) {
+            self.next();
+            return Ok(ParseTree::FunctionCall(Box::new(ParseTree::Identifier("∘".to_owned())), vec![composition, self.composition()?]));
+        }
+        Ok(composition)
+    }
     fn primary(&mut self) -> Result<ParseTree, ParseError<'a>> {
-        let mut primary = self.atom()?;
-        if let TokenKind::Caret = self.iter.peek().unwrap().kind {
-            self.iter.next();
+        let mut primary = self.composition()?;
+        if let (TokenKind::Caret, false) = (self.peek().kind, self.newline || self.closes_section()) {
+            self.next();
             primary = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("^".to_owned())), vec![primary, self.factor()?])
         }
         Ok(primary)
     }
     fn factor(&mut self) -> Result<ParseTree, ParseError<'a>> {
-        if let TokenKind::Plus | TokenKind::Minus = self.iter.peek().unwrap().kind {
+        if let TokenKind::Plus | TokenKind::Minus = self.peek().kind {
             let mut parser = self.clone();
-            let token = parser.iter.next().unwrap();
-            if let TokenKind::Star | TokenKind::Slash | TokenKind::Percent | TokenKind::Caret = parser.iter.peek().unwrap().kind {
+            let token = parser.next();
+            if let TokenKind::Star | TokenKind::Slash | TokenKind::Percent | TokenKind::Caret = parser.peek().kind {
                 let mut parser = parser.clone();
                 if let (Ok(_), Ok(_)) = (parser.factor(), parser.factor()) {
                     return self.primary();
@@ -139,14 +885,26 @@ impl<'a> Parser<'a> {
             } else {
                 self.primary()
             }
+        } else if let TokenKind::Exclamation = self.peek().kind {
+            let mut parser = self.clone();
+            parser.next();
+            if let Ok(factor) = parser.factor() {
+                *self = parser;
+                Ok(ParseTree::FunctionCall(Box::new(ParseTree::Identifier("!".to_owned())), vec![factor]))
+            } else {
+                self.primary()
+            }
         } else {
             self.primary()
         }
     }
     fn term(&mut self) -> Result<ParseTree, ParseError<'a>> {
         let mut term = self.factor()?;
-        while let TokenKind::Star | TokenKind::Slash | TokenKind::Percent = self.iter.peek().unwrap().kind {
-            match self.iter.next().unwrap().kind {
+        while let (TokenKind::Star | TokenKind::Slash | TokenKind::Percent, false) = (self.peek().kind, self.newline) {
+            if self.closes_section() {
+                break;
+            }
+            match self.next().kind {
                 TokenKind::Star => term = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("*".to_owned())), vec![term, self.factor()?]),
                 TokenKind::Slash => term = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("/".to_owned())), vec![term, self.factor()?]),
                 _ => term = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("%".to_owned())), vec![term, self.factor()?]),
@@ -154,33 +912,321 @@ impl<'a> Parser<'a> {
         }
         Ok(term)
     }
-    fn expr(&mut self) -> Result<ParseTree, ParseError<'a>> {
-        let mut expr = self.term()?;
-        while let TokenKind::Plus | TokenKind::Minus = self.iter.peek().unwrap().kind {
-            match self.iter.next().unwrap().kind {
-                TokenKind::Plus => expr = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("+".to_owned())), vec![expr, self.term()?]),
-                _ => expr = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("-".to_owned())), vec![expr, self.term()?]),
+    fn arithmetic(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut arithmetic = self.term()?;
+        while let (TokenKind::Plus | TokenKind::Minus, false) = (self.peek().kind, self.newline) {
+            if self.closes_section() {
+                break;
+            }
+            match self.next().kind {
+                TokenKind::Plus => arithmetic = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("+".to_owned())), vec![arithmetic, self.term()?]),
+                _ => arithmetic = ParseTree::FunctionCall(Box::new(ParseTree::Identifier("-".to_owned())), vec![arithmetic, self.term()?]),
             }
         }
+        Ok(arithmetic)
+    }
+    fn comparison(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let comparison = self.arithmetic()?;
+        if let (TokenKind::DotDot, false) = (self.peek().kind, self.newline) {
+            self.next();
+            let end = self.arithmetic()?;
+            let step = match (self.peek().kind, self.newline) {
+                (TokenKind::Colon, false) => {
+                    self.next();
+                    Some(Box::new(self.arithmetic()?))
+                },
+                _ => None,
+            };
+            return Ok(ParseTree::Range(Box::new(comparison), Box::new(end), step));
+        }
+        let operator = match (self.peek().kind, self.newline) {
+            (TokenKind::EqualEqual, false) => "==",
+            (TokenKind::ExclamationEqual, false) => "!=",
+            (TokenKind::Less, false) => "<",
+            (TokenKind::LessEqual, false) => "<=",
+            (TokenKind::Greater, false) => ">",
+            (TokenKind::GreaterEqual, false) => ">=",
+            _ => return Ok(comparison),
+        };
+        if self.closes_section() {
+            return Ok(comparison);
+        }
+        self.next();
+        Ok(ParseTree::FunctionCall(Box::new(ParseTree::Identifier(operator.to_owned())), vec![comparison, self.arithmetic()?]))
+    }
+    fn conjunction(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut conjunction = self.comparison()?;
+        while let (TokenKind::AmpersandAmpersand, false) = (self.peek().kind, self.newline) {
+            self.next();
+            conjunction = ParseTree::And(Box::new(conjunction), Box::new(self.comparison()?));
+        }
+        Ok(conjunction)
+    }
+    fn disjunction(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut disjunction = self.conjunction()?;
+        while let (TokenKind::PipePipe, false) = (self.peek().kind, self.newline) {
+            self.next();
+            disjunction = ParseTree::Or(Box::new(disjunction), Box::new(self.conjunction()?));
+        }
+        Ok(disjunction)
+    }
+    fn pipeline(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut pipeline = self.disjunction()?;
+        while let (TokenKind::PipeGreater, false) = (self.peek().kind, self.newline) {
+            self.next();
+            pipeline = ParseTree::Pipe(Box::new(pipeline), Box::new(self.disjunction()?));
+        }
+        Ok(pipeline)
+    }
+    fn parameters(&mut self) -> Option<Vec<String>> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Identifier => Some(vec![token.lexeme.to_owned()]),
+            TokenKind::LeftParenthesis => {
+                let mut params = Vec::new();
+                if let TokenKind::Identifier = self.peek().kind {
+                    params.push(self.next().lexeme.to_owned());
+                    while let TokenKind::Comma = self.peek().kind {
+                        self.next();
+                        let token = self.next();
+                        if token.kind != TokenKind::Identifier {
+                            return None;
+                        }
+                        params.push(token.lexeme.to_owned());
+                    }
+                }
+                if self.next().kind != TokenKind::RightParenthesis {
+                    return None;
+                }
+                Some(params)
+            },
+            _ => None,
+        }
+    }
+    fn expr(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut parser = self.clone();
+        let expr = match parser.parameters() {
+            Some(params) if parser.peek().kind == TokenKind::Arrow => {
+                parser.next();
+                *self = parser;
+                ParseTree::Lambda(params, Box::new(self.expr()?))
+            },
+            _ => self.pipeline()?,
+        };
+        if let TokenKind::Where = self.peek().kind {
+            self.next();
+            return Ok(ParseTree::Let(self.bindings()?, Box::new(expr)));
+        }
         Ok(expr)
     }
+    fn definition(&mut self) -> Option<(String, Vec<String>)> {
+        let token = self.next();
+        if token.kind != TokenKind::Identifier || self.peek().kind != TokenKind::LeftParenthesis {
+            return None;
+        }
+        let params = self.parameters()?;
+        if self.next().kind != TokenKind::Equal {
+            return None;
+        }
+        Some((token.lexeme.to_owned(), params))
+    }
+    fn pattern(&mut self) -> Option<Pattern> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Identifier => Some(Pattern::Name(token.lexeme.to_owned())),
+            TokenKind::Number => token.lexeme.parse().ok().map(|num| Pattern::Literal(ParseTree::number(num))),
+            TokenKind::String => unescape(token.lexeme).map(|string| Pattern::Literal(ParseTree::String(string))),
+            TokenKind::Minus => match self.pattern()? {
+                Pattern::Literal(num @ ParseTree::Number(_, _)) => Some(Pattern::Literal(ParseTree::FunctionCall(Box::new(ParseTree::Identifier("-".to_owned())), vec![num]))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    fn clause(&mut self) -> Option<(String, Vec<Pattern>)> {
+        let token = self.next();
+        if token.kind != TokenKind::Identifier || self.next().kind != TokenKind::LeftParenthesis {
+            return None;
+        }
+        let mut patterns = vec![self.pattern()?];
+        while let TokenKind::Comma = self.peek().kind {
+            self.next();
+            patterns.push(self.pattern()?);
+        }
+        if self.next().kind != TokenKind::RightParenthesis || self.next().kind != TokenKind::Equal {
+            return None;
+        }
+        Some((token.lexeme.to_owned(), patterns))
+    }
+    fn module(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let mut module = ParseTree::Identifier(self.expect(TokenKind::Identifier, &[TokenKind::Identifier])?.lexeme.to_owned());
+        while let TokenKind::Dot = self.peek().kind {
+            self.next();
+            let name = self.expect(TokenKind::Identifier, &[TokenKind::Identifier])?.lexeme.to_owned();
+            module = ParseTree::Field(Box::new(module), name);
+        }
+        Ok(module)
+    }
+    fn import(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        let token = self.expect(TokenKind::String, &[TokenKind::String])?;
+        unescape(token.lexeme).map(ParseTree::Import).ok_or(ParseError {
+            expected: &[],
+            found: token,
+            reason: None,
+        })
+    }
+    fn statement(&mut self) -> Result<ParseTree, ParseError<'a>> {
+        match self.peek().kind {
+            TokenKind::Use => {
+                self.next();
+                return Ok(ParseTree::Use(Box::new(self.module()?)));
+            },
+            TokenKind::Import => {
+                self.next();
+                return self.import();
+            },
+            TokenKind::Rec => {
+                self.next();
+                return self.group();
+            },
+            _ => (),
+        }
+        let mut parser = self.clone();
+        if let Some((name, params)) = parser.definition() {
+            *self = parser;
+            return Ok(ParseTree::Definition(name, params, Box::new(self.expr()?)));
+        }
+        let mut parser = self.clone();
+        if let Some((name, patterns)) = parser.clause() {
+            *self = parser;
+            return Ok(ParseTree::Clauses(name, vec![(patterns, self.expr()?)]));
+        }
+        let mut parser = self.clone();
+        let token = parser.next();
+        if let (TokenKind::Identifier, TokenKind::Equal) = (token.kind, parser.next().kind) {
+            *self = parser;
+            return Ok(ParseTree::Assignment(token.lexeme.to_owned(), Box::new(self.expr()?)));
+        }
+        let mut parser = self.clone();
+        if let (TokenKind::LeftParenthesis, Some(names), TokenKind::Equal) = (parser.peek().kind, parser.parameters(), parser.next().kind) {
+            *self = parser;
+            return Ok(ParseTree::Destructuring(names, Box::new(self.expr()?)));
+        }
+        self.expr()
+    }
     pub fn parse(&mut self) -> Result<ParseTree, ParseError<'a>> {    
-        let tree = self.expr()?;
-        let token = self.iter.next().unwrap();
+        let tree = self.statement()?;
+        let token = self.next();
         if let TokenKind::EndOfFile = token.kind {
             Ok(tree)
         } else {
             Err(ParseError {
                 expected: &[TokenKind::EndOfFile],
                 found: token,
+                reason: None,
             })
         }
     }
+    pub fn parse_program(&mut self) -> Result<Program, ParseError<'a>> {
+        let mut statements = Vec::new();
+        loop {
+            while let TokenKind::Semicolon = self.peek().kind {
+                self.next();
+            }
+            if let (TokenKind::EndOfFile, false) = (self.peek().kind, statements.is_empty()) {
+                return Ok(Program { statements });
+            }
+            let first = self.peek();
+            let statement = self.statement()?;
+            merge(&mut statements, statement, first)?;
+            let token = self.peek();
+            if !matches!(token.kind, TokenKind::Semicolon | TokenKind::EndOfFile) && !self.newline {
+                return Err(ParseError {
+                    expected: &[TokenKind::Semicolon, TokenKind::EndOfFile],
+                    found: token,
+                    reason: None,
+                });
+            }
+        }
+    }
+}
+/// Adds a statement after the others, merging it into the definition before it when they are clauses
+/// of one function.
+fn merge<'a>(statements: &mut Vec<ParseTree>, statement: ParseTree, first: Token<'a>) -> Result<(), ParseError<'a>> {
+    let signature = statement.signature();
+    match (statements.pop(), signature) {
+        // Consecutive definitions of a function merge into one when either has pattern clauses.
+        (Some(last), Some((name, arity, patterns))) if last.signature().is_some_and(|other| other.0 == name && other.1 == arity && (patterns || other.2)) => {
+            let name = name.to_owned();
+            let mut clauses = last.into_clauses();
+            if clauses.iter().any(|(patterns, _)| patterns.iter().all(|pattern| matches!(pattern, Pattern::Name(_)))) {
+                return Err(ParseError {
+                    expected: &[],
+                    found: first,
+                    reason: Some(Reason::UnreachableClause),
+                });
+            }
+            clauses.extend(statement.into_clauses());
+            statements.push(ParseTree::Clauses(name, clauses));
+        },
+        (last, _) => {
+            statements.extend(last);
+            // Clauses split up by other statements would each be a partial function, so they are rejected.
+            if let Some((name, _, patterns)) = signature {
+                if statements.iter().filter_map(ParseTree::signature).any(|other| other.0 == name && (patterns || other.2)) {
+                    return Err(ParseError {
+                        expected: &[],
+                        found: first,
+                        reason: Some(Reason::SplitClauses),
+                    });
+                }
+            }
+            statements.push(statement);
+        },
+    }
+    Ok(())
 }
 impl<'a> From<&'a str> for Parser<'a> {
     fn from(string: &'a str) -> Self {
         Self {
+            source: string,
             iter: Tokens::from(string).peekable(),
+            newline: false,
+            groups: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(string: &str) -> String {
+        Parser::from(string).parse_program().err().map(|err| err.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn adjacent_clauses_merge_into_one_function() {
+        let program = Parser::from("f(0) = 1; f(n) = n * f(n - 1); f(5)").parse_program().unwrap();
+        assert_eq!(program.statements.len(), 2);
+        assert_eq!(program.statements[0].to_string(), "f(0) = 1; f(n) = n * f(n - 1)");
+        assert_eq!(Parser::from("f(x) = 1; f(x) = 2").parse_program().unwrap().statements.len(), 2);
+    }
+
+    #[test]
+    fn split_and_unreachable_clauses_are_rejected() {
+        assert_eq!(error("f(0) = 1; g(x) = 2; f(n) = 3"), "clauses of f must be adjacent");
+        assert_eq!(error("f(0) = 1; x = 2; f(1) = 3"), "clauses of f must be adjacent");
+        assert_eq!(error("f(n) = n; f(0) = 1; f(0)"), "clause of f follows one matching any arguments, so it is never tried");
+        assert_eq!(error("f(0) = 1; f(n) = n; f(m) = 2"), "clause of f follows one matching any arguments, so it is never tried");
+        assert_eq!(error("f(0) = 1; f(1) = 2"), "");
+    }
+
+    #[test]
+    fn rec_groups_only_define_distinct_functions() {
+        assert_eq!(error("rec { x = 1 }"), "rec groups may only define functions");
+        assert_eq!(error("rec { f(x) = 1; f(x) = 2 }"), "f is defined twice in a rec group");
+        assert_eq!(error("rec { f(0) = 1; g(x) = 2; f(n) = 3 }"), "clauses of f must be adjacent");
+        assert_eq!(error("rec { f(0) = 1\n  f(n) = g(n)\n  g(n) = f(n - 1) }"), "");
+    }
+}
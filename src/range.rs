@@ -0,0 +1,108 @@
+use crate::{decimal::{Context, Decimal, Rounding}, expr::*};
+use std::fmt::Display;
+
+/// The number of items from `start` to `end` inclusive in steps of `step`, or `None` if they are not
+/// numbers, the step is zero or the count does not fit an `i64`, as positions into the items must.
+/// Decimals are counted exactly, and floats against the items as they are computed, so `0..0.3:0.1`
+/// stops at `0.2` because `3 * 0.1` is past `0.3`.
+pub fn steps(start: &Value, end: &Value, step: &Value) -> Option<usize> {
+    let context = [start, end, step].into_iter().find_map(|value| match value {
+        Value::Decimal(num) => Some(num.context()),
+        _ => None,
+    });
+    let last = match context {
+        Some(context) => decimal_steps(&exact(start, context)?, &exact(end, context)?, &exact(step, context)?)?,
+        None => float_steps(float(start)?, float(end)?, float(step)?)?,
+    };
+    usize::try_from(last.checked_add(1)?).ok()
+}
+fn exact(value: &Value, context: Context) -> Option<Decimal> {
+    match value {
+        Value::Decimal(num) => Some(num.clone()),
+        Value::Number(num) => Decimal::from_f64(*num, context),
+        _ => None,
+    }
+}
+/// The last position, rounding toward it at enough digits that it is found exactly whenever it fits.
+fn decimal_steps(start: &Decimal, end: &Decimal, step: &Decimal) -> Option<i64> {
+    if step.is_zero() {
+        return None;
+    }
+    let working = Context::new(start.context().precision + 20, Rounding::Floor);
+    let (from, to) = if step.is_negative() { (end, start) } else { (start, end) };
+    let distance = to.with_context(working)?.checked_sub(&from.with_context(working)?)?;
+    if distance.is_negative() {
+        return Some(-1);
+    }
+    distance.checked_div(&step.abs().with_context(working)?)?.quantize(0, Rounding::Floor)?.to_i64()
+}
+fn float_steps(start: f64, end: f64, step: f64) -> Option<i64> {
+    let last = ((end - start) / step).floor();
+    if step == 0.0 || !last.is_finite() {
+        return None;
+    }
+    if last < 0.0 {
+        return Some(-1);
+    }
+    if last >= i64::MAX as f64 {
+        return None;
+    }
+    // The quotient may be off by one from the items, whose own rounding can put them either side of the end.
+    let past = |position: i64| {
+        let item = start + position as f64 * step;
+        if step > 0.0 { item > end } else { item < end }
+    };
+    let last = last as i64;
+    Some(if past(last) { last - 1 } else if !past(last + 1) { last + 1 } else { last })
+}
+
+/// An inclusive arithmetic sequence whose items are only computed when they are needed.
+#[derive(Clone)]
+pub struct Range {
+    start: Value,
+    end: Value,
+    step: Value,
+    len: usize,
+}
+impl Range {
+    /// The numbers from `start` to `end` inclusive in steps of `step`, or `None` if [`steps`] cannot
+    /// count them.
+    pub fn new(start: Value, end: Value, step: Value) -> Option<Self> {
+        Some(Self {
+            len: steps(&start, &end, &step)?,
+            start,
+            end,
+            step,
+        })
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn get(&self, position: usize) -> Option<Value> {
+        if position >= self.len {
+            return None;
+        }
+        let offset = star(vec![Value::Number(position as f64), self.step.clone()]).ok()?;
+        plus(vec![self.start.clone(), offset]).ok()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = Value> + '_ {
+        (0..self.len).filter_map(|position| self.get(position))
+    }
+}
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bound = |value: &Value| match value {
+            Value::Number(num) => num.to_string(),
+            Value::Decimal(num) => num.to_string(),
+            value => format!("{:?}", value),
+        };
+        write!(f, "{}..{}", bound(&self.start), bound(&self.end))?;
+        match float(&self.step) {
+            Some(1.0) => Ok(()),
+            _ => write!(f, ":{}", bound(&self.step)),
+        }
+    }
+}
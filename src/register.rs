@@ -0,0 +1,278 @@
+use crate::{decimal::Decimal, expr::*, lifted::Lifted};
+use std::{collections::HashMap, error::Error, fmt::Display, rc::Rc};
+
+#[derive(Debug, Clone)]
+pub struct TypeMismatch {
+    pub function: Option<String>,
+    pub position: usize,
+    pub expected: &'static str,
+    pub found: Value,
+}
+impl Display for TypeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(function) = &self.function {
+            write!(f, "{} ", function)?;
+        }
+        let article = if self.expected.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+        write!(f, "expected argument {} to be {} {} but found {:?}", self.position + 1, article, self.expected, self.found)
+    }
+}
+impl Error for TypeMismatch {}
+
+/// A Rust type that function arguments can be converted to.
+pub trait FromValue: Sized {
+    const TYPE: &'static str;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+impl FromValue for Value {
+    const TYPE: &'static str = "value";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+impl FromValue for f64 {
+    const TYPE: &'static str = "number";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(num) => Some(*num),
+            Value::Decimal(num) => Some(num.to_f64()),
+            _ => None,
+        }
+    }
+}
+impl FromValue for i64 {
+    const TYPE: &'static str = "integer";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        integer(value)
+    }
+}
+impl FromValue for Decimal {
+    const TYPE: &'static str = "decimal";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Decimal(num) => Some(num.clone()),
+            _ => None,
+        }
+    }
+}
+impl FromValue for bool {
+    const TYPE: &'static str = "boolean";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(boolean) => Some(*boolean),
+            _ => None,
+        }
+    }
+}
+impl FromValue for String {
+    const TYPE: &'static str = "string";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(string) => Some(string.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// A Rust type that function results can be converted from.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Number(self as f64)
+    }
+}
+impl IntoValue for Decimal {
+    fn into_value(self) -> Value {
+        Value::Decimal(self)
+    }
+}
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+impl<A: IntoValue, B: IntoValue> IntoValue for (A, B) {
+    fn into_value(self) -> Value {
+        Value::Tuple(Rc::from([self.0.into_value(), self.1.into_value()]))
+    }
+}
+
+/// A function result, which is either a value or a `Result` of one.
+pub trait IntoResult {
+    fn into_result(self) -> Result<Value, Box<dyn Error>>;
+}
+impl<T: IntoValue> IntoResult for T {
+    fn into_result(self) -> Result<Value, Box<dyn Error>> {
+        Ok(self.into_value())
+    }
+}
+impl<T: IntoValue, E: Into<Box<dyn Error>>> IntoResult for Result<T, E> {
+    fn into_result(self) -> Result<Value, Box<dyn Error>> {
+        self.map(IntoValue::into_value).map_err(Into::into)
+    }
+}
+
+/// A Rust closure whose arity and argument types are checked when called from an expression.
+pub trait TypedFunction<Args>: 'static {
+    /// The type of each parameter.
+    const PARAMS: &'static [&'static str];
+
+    fn call(&self, args: Vec<Value>) -> Result<Value, Box<dyn Error>>;
+}
+fn argument<T: FromValue>(position: usize, value: Value) -> Result<T, Box<dyn Error>> {
+    match T::from_value(&value) {
+        Some(arg) => Ok(arg),
+        None => Err(Box::new(TypeMismatch { function: None, position, expected: T::TYPE, found: value })),
+    }
+}
+macro_rules! typed_function {
+    ($($arg:ident),+) => {
+        impl<F, R, $($arg),+> TypedFunction<($($arg,)+)> for F
+        where
+            F: Fn($($arg),+) -> R + 'static,
+            R: IntoResult,
+            $($arg: FromValue),+
+        {
+            const PARAMS: &'static [&'static str] = &[$($arg::TYPE),+];
+
+            fn call(&self, args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+                let arity = Self::PARAMS.len();
+                if args.len() != arity {
+                    return Err(Box::new(ArityMismatch { function: None, expected: arity..=arity, found: args.len() }));
+                }
+                let mut args = args.into_iter().enumerate();
+                self($({
+                    let (position, value) = args.next().unwrap();
+                    argument::<$arg>(position, value)?
+                }),+).into_result()
+            }
+        }
+    };
+}
+typed_function!(A);
+typed_function!(A, B);
+typed_function!(A, B, C);
+typed_function!(A, B, C, D);
+
+/// A named function whose parameters are known from its Rust signature. Like [`Function::native`],
+/// it is assumed to be pure.
+pub fn function<Args, F: TypedFunction<Args>>(name: &str, func: F) -> Function {
+    Function {
+        name: Some(name.to_owned()),
+        arity: Some(F::PARAMS.len()..=F::PARAMS.len()),
+        params: Some(F::PARAMS.iter().map(|param| param.to_string()).collect()),
+        pure: true,
+        ..Function::new(move |args| func.call(args))
+    }
+}
+
+fn lifted(func: &Rc<Function>, args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    if !args.iter().any(|arg| matches!(arg, Value::Function(_))) {
+        return func.call(args);
+    }
+    Ok(Value::Function(Rc::new(Function::lifted(Lifted::Apply(func.clone(), args.iter().map(Lifted::from_value).collect())))))
+}
+/// Lifts a function pointwise over function arguments, so that e.g. `f(sin, 2)` is `x -> f(sin(x), 2)`.
+pub fn lift(func: Function) -> Function {
+    let func = Rc::new(func);
+    Function {
+        name: func.name.clone(),
+        arity: func.arity.clone(),
+        params: func.params.clone(),
+        doc: func.doc.clone(),
+        pure: func.pure,
+        derivative: func.derivative,
+        partial: func.partial,
+        body: Body::Native(Box::new(move |args| lifted(&func, args))),
+    }
+}
+
+/// Registration of typed Rust closures into a table or module.
+pub trait Register {
+    fn register<Args>(&mut self, name: &str, func: impl TypedFunction<Args>);
+    fn register_lifted<Args>(&mut self, name: &str, func: impl TypedFunction<Args>);
+}
+impl Register for HashMap<String, Value> {
+    fn register<Args>(&mut self, name: &str, func: impl TypedFunction<Args>) {
+        self.insert(name.to_owned(), Value::Function(Rc::new(function(name, func))));
+    }
+    fn register_lifted<Args>(&mut self, name: &str, func: impl TypedFunction<Args>) {
+        self.insert(name.to_owned(), Value::Function(Rc::new(lift(function(name, func)))));
+    }
+}
+impl Register for Record {
+    fn register<Args>(&mut self, name: &str, func: impl TypedFunction<Args>) {
+        self.insert(name, Value::Function(Rc::new(function(name, func))));
+    }
+    fn register_lifted<Args>(&mut self, name: &str, func: impl TypedFunction<Args>) {
+        self.insert(name, Value::Function(Rc::new(lift(function(name, func)))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(string: &str) -> String {
+        let mut expr = Expression::try_from(string).unwrap();
+        expr.table.register("scale", |x: f64, k: i64| x * k as f64);
+        expr.table.register("greet", |name: String| format!("hi {}", name));
+        expr.table.register("halve", |n: i64| if n % 2 == 0 { Ok(n / 2) } else { Err(format!("{} is odd", n)) });
+        expr.table.register_lifted("double", |x: f64| 2.0 * x);
+        match expr.eval() {
+            Ok(value) => format!("{:?}", value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn arguments_are_checked_against_the_signature() {
+        assert_eq!(eval("scale(1.5, 2)"), "Number(3)");
+        assert_eq!(eval("greet(\"you\")"), "String(\"hi you\")");
+        assert_eq!(eval("scale"), "Function(scale(number, integer))");
+        assert_eq!(eval("scale(1.5, 2.5)"), "function call failure: scale(number, integer) expected argument 2 to be an integer but found Number(2.5)");
+        assert_eq!(eval("scale(1.5)"), "function call failure: scale(number, integer) expected 2 arguments but found 1");
+        assert_eq!(eval("halve(4) + 1"), "Number(3)");
+        assert_eq!(eval("halve(3)"), "function call failure: 3 is odd");
+    }
+
+    #[test]
+    fn lifted_functions_apply_pointwise() {
+        assert_eq!(eval("double(3)"), "Number(6)");
+        assert_eq!(eval("double(x -> x + 1)(2)"), "Number(6)");
+    }
+}
@@ -0,0 +1,372 @@
+use crate::{decimal::{Context, Decimal, Rounding}, expr::*, lifted::Lifted};
+use std::{cmp::Ordering, collections::HashMap, error::Error, rc::Rc};
+
+/// Applies a one-argument function to a number, a decimal or each item of a list.
+fn unary(args: Vec<Value>, float: fn(f64) -> f64, decimal: fn(&Decimal) -> Option<Decimal>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [Value::Number(num)] => Ok(Value::Number(float(*num))),
+        [Value::Decimal(num)] => match decimal(num) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(InvalidArguments::new(args))),
+        },
+        [Value::List(items)] => Ok(Value::List(items.iter().map(|item| unary(vec![item.clone()], float, decimal)).collect::<Result<_, _>>()?)),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn binary(args: Vec<Value>, float: fn(f64, f64) -> f64, decimal: fn(&Decimal, &Decimal) -> Option<Decimal>) -> Result<Value, Box<dyn Error>> {
+    if let Some((num1, num2)) = decimals(&args) {
+        return match decimal(&num1, &num2) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(InvalidArguments::new(args))),
+        };
+    }
+    match args.as_slice() {
+        [Value::Number(num1), Value::Number(num2)] => Ok(Value::Number(float(*num1, *num2))),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn compare(num1: &Value, num2: &Value) -> Option<Ordering> {
+    if let Some((num1, num2)) = decimals(&[num1.clone(), num2.clone()]) {
+        return Some(num1.cmp(&num2));
+    }
+    match (num1, num2) {
+        (Value::Number(num1), Value::Number(num2)) => Some(num1.total_cmp(num2)),
+        _ => None,
+    }
+}
+/// Finds the item furthest in the `wanted` direction among the arguments, or the items of a single list.
+fn extremum(args: Vec<Value>, wanted: Ordering) -> Result<Value, Box<dyn Error>> {
+    let items = match args.as_slice() {
+        [Value::List(items)] => items.to_vec(),
+        _ => args.clone(),
+    };
+    let mut iter = items.iter();
+    let mut best = match iter.next() {
+        Some(best) if compare(best, best).is_some() => best,
+        _ => return Err(Box::new(InvalidArguments::new(args))),
+    };
+    for item in iter {
+        match compare(item, best) {
+            Some(ordering) if ordering == wanted => best = item,
+            Some(_) => (),
+            None => return Err(Box::new(InvalidArguments::new(args))),
+        }
+    }
+    Ok(best.clone())
+}
+
+fn sqrt(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::sqrt, Decimal::sqrt)
+}
+fn cbrt(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::cbrt, Decimal::cbrt)
+}
+fn exp(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::exp, Decimal::exp)
+}
+fn ln(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::ln, Decimal::ln)
+}
+fn log(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    binary(args, |base, num| match base {
+        2.0 => num.log2(),
+        10.0 => num.log10(),
+        _ => num.log(base),
+    }, |base, num| num.log(base))
+}
+fn abs(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::abs, |num| Some(num.abs()))
+}
+fn sign(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, |num| if num > 0.0 { 1.0 } else if num < 0.0 { -1.0 } else { num }, |num| {
+        Some(Decimal::from_i64(if num.is_zero() { 0 } else if num.is_negative() { -1 } else { 1 }, num.context()))
+    })
+}
+fn floor(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::floor, |num| num.quantize(0, Rounding::Floor))
+}
+fn ceil(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::ceil, |num| num.quantize(0, Rounding::Ceiling))
+}
+fn trunc(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::trunc, |num| num.quantize(0, Rounding::Down))
+}
+/// Rounds half away from zero, to an integer or to the given number of digits after the point.
+fn round(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let digits = match args.as_slice() {
+        [_] => return unary(args, f64::round, |num| num.quantize(0, Rounding::HalfUp)),
+        [_, digits] => integer(digits),
+        _ => None,
+    };
+    match (args.as_slice(), digits) {
+        // A float has nothing to round past its 17 significant digits, and keeps nothing more than 308
+        // digits before the point, beyond which its scale would not be finite either.
+        ([Value::Number(num), _], Some(digits)) if !num.is_finite() || *num == 0.0 || digits as f64 + num.abs().log10().floor() >= 17.0 => Ok(Value::Number(*num)),
+        ([Value::Number(num), _], Some(digits)) if digits < -308 => Ok(Value::Number(0f64.copysign(*num))),
+        ([Value::Number(num), _], Some(digits)) => {
+            let scale = 10f64.powi(digits.min(308) as i32);
+            Ok(Value::Number((num * scale).round() / scale))
+        },
+        ([Value::Decimal(num), _], Some(digits)) => match digits.checked_neg().and_then(|digits| num.quantize(digits, Rounding::HalfUp)) {
+            Some(num) => Ok(Value::Decimal(num)),
+            None => Err(Box::new(InvalidArguments::new(args))),
+        },
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn min(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    extremum(args, Ordering::Less)
+}
+fn max(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    extremum(args, Ordering::Greater)
+}
+fn clamp(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    let [num, low, high] = args.as_slice() else {
+        return Err(Box::new(InvalidArguments::new(args)));
+    };
+    match (compare(num, low), compare(num, high)) {
+        (Some(Ordering::Less), Some(_)) => Ok(low.clone()),
+        (Some(_), Some(Ordering::Greater)) => Ok(high.clone()),
+        (Some(_), Some(_)) => Ok(num.clone()),
+        _ => Err(Box::new(InvalidArguments::new(args))),
+    }
+}
+fn hypot(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    binary(args, f64::hypot, Decimal::hypot)
+}
+fn atan2(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    binary(args, f64::atan2, Decimal::atan2)
+}
+fn sin(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::sin, Decimal::sin)
+}
+fn cos(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::cos, Decimal::cos)
+}
+fn tan(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::tan, Decimal::tan)
+}
+fn asin(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::asin, Decimal::asin)
+}
+fn acos(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::acos, Decimal::acos)
+}
+fn atan(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::atan, Decimal::atan)
+}
+fn sinh(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::sinh, Decimal::sinh)
+}
+fn cosh(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::cosh, Decimal::cosh)
+}
+fn tanh(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::tanh, Decimal::tanh)
+}
+fn asinh(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::asinh, Decimal::asinh)
+}
+fn acosh(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::acosh, Decimal::acosh)
+}
+fn atanh(args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    unary(args, f64::atanh, Decimal::atanh)
+}
+
+type Native = fn(Vec<Value>) -> Result<Value, Box<dyn Error>>;
+/// The name, parameters, documentation, implementation and derivative of a function.
+type Builtin = (&'static str, &'static [&'static str], &'static str, Native, Option<fn() -> Lifted>);
+
+const FUNCTIONS: &[Builtin] = &[
+    ("sqrt", &["x"], "The square root of x.", sqrt, Some(|| num(1.0) / (num(2.0) * builtin("sqrt")))),
+    ("cbrt", &["x"], "The cube root of x.", cbrt, Some(|| num(1.0) / (num(3.0) * builtin("cbrt").pow(num(2.0))))),
+    ("exp", &["x"], "e raised to the power x.", exp, Some(|| builtin("exp"))),
+    ("ln", &["x"], "The natural logarithm of x.", ln, Some(|| num(1.0) / Lifted::Argument)),
+    ("log", &["base", "x"], "The logarithm of x to the given base.", log, None),
+    ("abs", &["x"], "The absolute value of x.", abs, Some(|| builtin("sign"))),
+    ("sign", &["x"], "-1, 0 or 1 according to the sign of x.", sign, Some(|| num(0.0))),
+    ("floor", &["x"], "The greatest integer at most x.", floor, Some(|| num(0.0))),
+    ("ceil", &["x"], "The least integer at least x.", ceil, Some(|| num(0.0))),
+    ("round", &["x", "digits?"], "Rounds x half away from zero, to an integer or to the given number of digits after the point.", round, None),
+    ("trunc", &["x"], "The integer part of x.", trunc, Some(|| num(0.0))),
+    ("min", &["values..."], "The least of the arguments, or of the items of a single list.", min, None),
+    ("max", &["values..."], "The greatest of the arguments, or of the items of a single list.", max, None),
+    ("clamp", &["x", "low", "high"], "x limited to the range from low to high.", clamp, None),
+    ("hypot", &["x", "y"], "The length of the hypotenuse of a right triangle with legs x and y.", hypot, None),
+    ("atan2", &["y", "x"], "The angle of the point (x, y) from the positive x axis, in radians.", atan2, None),
+    ("sin", &["x"], "The sine of x radians.", sin, Some(|| builtin("cos"))),
+    ("cos", &["x"], "The cosine of x radians.", cos, Some(|| -builtin("sin"))),
+    ("tan", &["x"], "The tangent of x radians.", tan, Some(|| num(1.0) / builtin("cos").pow(num(2.0)))),
+    ("asin", &["x"], "The arc sine of x, in radians.", asin, Some(|| num(1.0) / (num(1.0) - Lifted::Argument.pow(num(2.0))).pow(num(0.5)))),
+    ("acos", &["x"], "The arc cosine of x, in radians.", acos, Some(|| -(num(1.0) / (num(1.0) - Lifted::Argument.pow(num(2.0))).pow(num(0.5))))),
+    ("atan", &["x"], "The arc tangent of x, in radians.", atan, Some(|| num(1.0) / (num(1.0) + Lifted::Argument.pow(num(2.0))))),
+    ("sinh", &["x"], "The hyperbolic sine of x.", sinh, Some(|| builtin("cosh"))),
+    ("cosh", &["x"], "The hyperbolic cosine of x.", cosh, Some(|| builtin("sinh"))),
+    ("tanh", &["x"], "The hyperbolic tangent of x.", tanh, Some(|| num(1.0) / builtin("cosh").pow(num(2.0)))),
+    ("asinh", &["x"], "The inverse hyperbolic sine of x.", asinh, Some(|| num(1.0) / (Lifted::Argument.pow(num(2.0)) + num(1.0)).pow(num(0.5)))),
+    ("acosh", &["x"], "The inverse hyperbolic cosine of x.", acosh, Some(|| num(1.0) / (Lifted::Argument.pow(num(2.0)) - num(1.0)).pow(num(0.5)))),
+    ("atanh", &["x"], "The inverse hyperbolic tangent of x.", atanh, Some(|| num(1.0) / (num(1.0) - Lifted::Argument.pow(num(2.0))))),
+];
+fn builtin(name: &str) -> Lifted {
+    let &(name, params, doc, body, derivative) = FUNCTIONS.iter().find(|function| function.0 == name).expect("a known function");
+    Lifted::Function(Rc::new(Function { derivative, ..Function::native(name, params, doc, body) }))
+}
+fn num(num: f64) -> Lifted {
+    Lifted::Constant(Value::Number(num))
+}
+
+/// The value of the body, or of the fallback if evaluating the body fails.
+fn attempt(args: Vec<Thunk>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [body, fallback] => body.eval().or_else(|_| fallback.eval()).map_err(|err| err.into()),
+        _ => Err(Box::new(MalformedForm::default())),
+    }
+}
+/// The value of the body, or of the fallback if the body refers to an undefined identifier.
+fn default(args: Vec<Thunk>) -> Result<Value, Box<dyn Error>> {
+    match args.as_slice() {
+        [body, fallback] => match body.eval() {
+            Err(ExpressionError::UndefinedIdentifier(_)) => fallback.eval().map_err(|err| err.into()),
+            result => result.map_err(|err| err.into()),
+        },
+        _ => Err(Box::new(MalformedForm::default())),
+    }
+}
+/// Describes a function or form by its signature and documentation, or each one in a module as a
+/// list. Other values have no description.
+pub fn help(value: &Value) -> Option<Value> {
+    match value {
+        Value::Function(func) => Some(Value::String(func.to_string().into())),
+        Value::Form(form) => Some(Value::String(form.to_string().into())),
+        Value::Record(record) => Some(Value::List(record.iter().filter_map(|(_, value)| match value {
+            Value::Function(_) | Value::Form(_) => help(value),
+            _ => None,
+        }).collect())),
+        _ => None,
+    }
+}
+
+/// Computes a constant with extra digits and rounds it once to the context.
+fn constant(context: Context, f: fn(Context) -> Option<Decimal>) -> Option<Decimal> {
+    f(Context::new(context.precision + 10, Rounding::HalfEven))?.with_context(context)
+}
+
+/// Installs the math constants and functions into `table`, both under their own names and
+/// grouped in a `math` module, along with `try`, `default` and `help`. `inf` and `nan` are floats
+/// in either mode, as decimals have no infinities.
+pub fn install(table: &mut HashMap<String, Value>, mode: Mode) {
+    let floats = [std::f64::consts::PI, std::f64::consts::TAU, std::f64::consts::E, (1.0 + 5f64.sqrt()) / 2.0];
+    let decimals: [fn(Context) -> Option<Decimal>; 4] = [
+        Decimal::pi,
+        |context| Decimal::pi(context)?.checked_mul(&Decimal::from_i64(2, context)),
+        |context| Decimal::from_i64(1, context).exp(),
+        |context| {
+            let one = Decimal::from_i64(1, context);
+            one.checked_add(&Decimal::from_i64(5, context).sqrt()?)?.checked_div(&Decimal::from_i64(2, context))
+        },
+    ];
+    let [pi, tau, e, phi] = std::array::from_fn(|i| match mode {
+        Mode::Decimal(context) => constant(context, decimals[i]).map_or(Value::Number(floats[i]), Value::Decimal),
+        Mode::Float => Value::Number(floats[i]),
+    });
+    let mut math = Record::new();
+    for (name, value) in [
+        ("π", pi),
+        ("τ", tau),
+        ("e", e),
+        ("φ", phi),
+        ("inf", Value::Number(f64::INFINITY)),
+        ("nan", Value::Number(f64::NAN)),
+    ] {
+        math.insert(name, value.clone());
+        table.insert(name.to_owned(), value);
+    }
+    for &(name, params, doc, body, derivative) in FUNCTIONS {
+        let value = Value::Function(Rc::new(Function { derivative, ..Function::native(name, params, doc, body) }));
+        math.insert(name, value.clone());
+        table.insert(name.to_owned(), value);
+    }
+    table.insert("math".to_owned(), Value::record(math));
+    table.insert("try".to_owned(), Value::form("try", &["body", "fallback"], "The value of body, or of fallback if evaluating body fails.", attempt));
+    table.insert("default".to_owned(), Value::form("default", &["body", "fallback"], "The value of body, or of fallback if body refers to an undefined identifier.", default));
+    table.insert("help".to_owned(), Value::native("help", &["value"], "Describes a function or form, or each one in a module.", |args| {
+        match args.as_slice() {
+            [value] => match help(value) {
+                Some(description) => Ok(description),
+                None => Err(Box::new(InvalidArguments::new(args))),
+            },
+            _ => Err(Box::new(InvalidArguments::new(args))),
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(string: &str) -> String {
+        eval_in(string, Mode::Float)
+    }
+    fn eval_in(string: &str, mode: Mode) -> String {
+        let mut expr = Expression::try_from(string).unwrap();
+        expr.mode = mode;
+        install(&mut expr.table, expr.mode);
+        match expr.eval() {
+            Ok(value) => format!("{:?}", value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn try_and_default_are_installed() {
+        assert_eq!(eval("try(1 / \"a\", 2)"), "Number(2)");
+        assert_eq!(eval("default(missing, 3)"), "Number(3)");
+        assert!(eval("default(1 / \"a\", 3)").ends_with("invalid arguments [Number(1), String(\"a\")] to /(x, y)"));
+    }
+
+    #[test]
+    fn help_describes_functions_forms_and_modules() {
+        assert_eq!(eval("help(sin)"), "String(\"sin(x): The sine of x radians.\")");
+        assert_eq!(eval("help(try)"), "String(\"try(body, fallback): The value of body, or of fallback if evaluating body fails.\")");
+        assert!(eval("help(math)").starts_with("List([String(\"sqrt(x): The square root of x.\")"));
+        let mut table = HashMap::new();
+        install(&mut table, Mode::Float);
+        assert!(matches!(help(&table["cos"]), Some(Value::String(doc)) if &*doc == "cos(x): The cosine of x radians."));
+        assert!(help(&Value::Number(1.0)).is_none());
+    }
+
+    #[test]
+    fn round_keeps_floats_it_cannot_scale() {
+        assert_eq!(eval("round(1.25, 1)"), "Number(1.3)");
+        assert_eq!(eval("round(1250, -2)"), "Number(1300)");
+        assert_eq!(eval("round(1.5, 400)"), "Number(1.5)");
+        assert_eq!(eval("round(0.1, 17)"), "Number(0.1)");
+        assert_eq!(eval("round(123456789.123, 12)"), "Number(123456789.123)");
+        assert_eq!(eval("round(1e300, -400)"), "Number(0)");
+        assert_eq!(eval("round(-1e300, -5000000000)"), "Number(-0)");
+        assert_eq!(eval("round(1.5, 4294967296)"), "Number(1.5)");
+    }
+
+    #[test]
+    fn modules_are_records_of_their_functions() {
+        assert_eq!(eval("math.sqrt(4)"), "Number(2)");
+        assert_eq!(eval("use math; sqrt(9)"), "Number(3)");
+        assert!(eval("math.nope").starts_with("undefined field \"nope\""));
+    }
+
+    #[test]
+    fn math_functions_work_in_both_modes() {
+        assert_eq!(eval("[sqrt(16), abs(-2), floor(-1.5), ceil(1.2)]"), "List([Number(4), Number(2), Number(-2), Number(2)])");
+        assert_eq!(eval("[min(3, 1, 2), max([1, 5, 2]), clamp(5, 0, 3), log(2, 8)]"), "List([Number(1), Number(5), Number(3), Number(3)])");
+        assert_eq!(eval("sin(π / 2)"), "Number(1)");
+        assert_eq!(eval("sqrt([4, 9])"), "List([Number(2), Number(3)])");
+        assert_eq!(eval("sqrt(\"a\")"), "function call failure: invalid arguments [String(\"a\")] to sqrt(x)");
+        let decimal = Mode::Decimal(Context::new(30, Rounding::HalfEven));
+        assert_eq!(eval_in("π", decimal), "Decimal(3.14159265358979323846264338328)");
+        assert_eq!(eval_in("exp(1) == e", decimal), "Boolean(true)");
+        assert_eq!(eval_in("sin(π / 6)", decimal), "Decimal(0.5)");
+        assert_eq!(eval_in("[round(2.5), round(1.2345, 2)]", decimal), "List([Decimal(3), Decimal(1.23)])");
+        assert_eq!(eval_in("ln(0)", decimal), "function call failure: invalid arguments [Decimal(0)] to ln(x)");
+    }
+}
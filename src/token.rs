@@ -8,7 +8,7 @@ pub struct Token<'a> {
 impl<'a> Display for Token<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.kind)?;
-        if let TokenKind::Unknown | TokenKind::Number | TokenKind::Identifier = self.kind {
+        if let TokenKind::Unknown | TokenKind::Number | TokenKind::String | TokenKind::Identifier = self.kind {
             write!(f, "({})", self.lexeme)?;
         }
         Ok(())
@@ -18,17 +18,50 @@ impl<'a> Display for Token<'a> {
 pub enum TokenKind {
     Unknown,
     EndOfFile,
+    Newline,
     Number,
+    String,
     Identifier,
+    If,
+    Then,
+    Else,
+    Let,
+    In,
+    Where,
+    Use,
+    Import,
+    Piecewise,
+    Rec,
     Plus,
     Minus,
     Star,
     Slash,
     Percent,
     Caret,
+    Equal,
+    EqualEqual,
+    ExclamationEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AmpersandAmpersand,
+    PipePipe,
+    PipeGreater,
+    Ring,
+    Exclamation,
+    Arrow,
     Comma,
+    Semicolon,
+    Colon,
+    Dot,
+    DotDot,
     LeftParenthesis,
     RightParenthesis,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
 }
 impl Display for TokenKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -55,18 +88,27 @@ impl<'a> Iterator for Tokens<'a> {
         match self.iter.next() {
             Some(ch) => match ch {
                 ch if ch.is_whitespace() => {
-                    while self.iter.clone().next().map_or(false, |ch| ch.is_whitespace()) {
+                    while self.iter.clone().next().is_some_and(|ch| ch.is_whitespace()) {
                         self.iter.next();
                     }
-                    self.next()
+                    let lexeme = &string[..string.len() - self.iter.as_str().len()];
+                    if lexeme.contains('\n') {
+                        Some(Token {
+                            lexeme,
+                            kind: TokenKind::Newline,
+                        })
+                    } else {
+                        self.next()
+                    }
                 },
-                ch if ch.is_digit(10) => {
-                    while self.iter.clone().next().map_or(false, |ch| ch.is_digit(10)) {
+                ch if ch.is_ascii_digit() => {
+                    while self.iter.clone().next().is_some_and(|ch| ch.is_ascii_digit()) {
                         self.iter.next();
                     }
-                    if let Some('.') = self.iter.clone().next() {
+                    let mut iter = self.iter.clone();
+                    if let (Some('.'), false) = (iter.next(), iter.next() == Some('.')) {
                         self.iter.next();
-                        while self.iter.clone().next().map_or(false, |ch| ch.is_digit(10)) {
+                        while self.iter.clone().next().is_some_and(|ch| ch.is_ascii_digit()) {
                             self.iter.next();
                         }
                     }
@@ -75,9 +117,9 @@ impl<'a> Iterator for Tokens<'a> {
                         if let Some('+') | Some('-') = iter.clone().next() {
                             iter.next();
                         }
-                        if iter.next().map_or(false, |ch| ch.is_digit(10)) {
+                        if iter.next().is_some_and(|ch| ch.is_ascii_digit()) {
                             self.iter = iter;
-                            while self.iter.clone().next().map_or(false, |ch| ch.is_digit(10)) {
+                            while self.iter.clone().next().is_some_and(|ch| ch.is_ascii_digit()) {
                                 self.iter.next();
                             }
                         }
@@ -87,9 +129,9 @@ impl<'a> Iterator for Tokens<'a> {
                         kind: TokenKind::Number,
                     })
                 },
-                '.' if self.iter.clone().next().map_or(false, |ch| ch.is_digit(10)) => {
+                '.' if self.iter.clone().next().is_some_and(|ch| ch.is_ascii_digit()) => {
                     self.iter.next();
-                    while self.iter.clone().next().map_or(false, |ch| ch.is_digit(10)) {
+                    while self.iter.clone().next().is_some_and(|ch| ch.is_ascii_digit()) {
                         self.iter.next();
                     }
                     let mut iter = self.iter.clone();
@@ -97,9 +139,9 @@ impl<'a> Iterator for Tokens<'a> {
                         if let Some('+') | Some('-') = iter.clone().next() {
                             iter.next();
                         }
-                        if iter.next().map_or(false, |ch| ch.is_digit(10)) {
+                        if iter.next().is_some_and(|ch| ch.is_ascii_digit()) {
                             self.iter = iter;
-                            while self.iter.clone().next().map_or(false, |ch| ch.is_digit(10)) {
+                            while self.iter.clone().next().is_some_and(|ch| ch.is_ascii_digit()) {
                                 self.iter.next();
                             }
                         }
@@ -109,13 +151,52 @@ impl<'a> Iterator for Tokens<'a> {
                         kind: TokenKind::Number,
                     })
                 },
+                '"' => {
+                    let mut kind = TokenKind::Unknown;
+                    while let Some(ch) = self.iter.next() {
+                        match ch {
+                            '\\' => {
+                                self.iter.next();
+                            },
+                            '"' => {
+                                kind = TokenKind::String;
+                                break;
+                            },
+                            _ => (),
+                        }
+                    }
+                    Some(Token {
+                        lexeme: &string[..string.len() - self.iter.as_str().len()],
+                        kind,
+                    })
+                },
                 ch if ch.is_alphabetic() || ch == '_' => {
-                    while self.iter.clone().next().map_or(false, |ch| ch.is_alphanumeric() || ch == '_') {
+                    while self.iter.clone().next().is_some_and(|ch| ch.is_alphanumeric() || ch == '_') {
                         self.iter.next();
                     }
+                    let lexeme = &string[..string.len() - self.iter.as_str().len()];
                     Some(Token {
-                        lexeme: &string[..string.len() - self.iter.as_str().len()],
-                        kind: TokenKind::Identifier,
+                        lexeme,
+                        kind: match lexeme {
+                            "if" => TokenKind::If,
+                            "then" => TokenKind::Then,
+                            "else" => TokenKind::Else,
+                            "let" => TokenKind::Let,
+                            "in" => TokenKind::In,
+                            "where" => TokenKind::Where,
+                            "use" => TokenKind::Use,
+                            "import" => TokenKind::Import,
+                            "piecewise" => TokenKind::Piecewise,
+                            "rec" => TokenKind::Rec,
+                            _ => TokenKind::Identifier,
+                        },
+                    })
+                },
+                '-' if self.iter.clone().next() == Some('>') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::Arrow,
                     })
                 },
                 '+' => Some(Token {
@@ -142,10 +223,90 @@ impl<'a> Iterator for Tokens<'a> {
                     lexeme: &string[..1],
                     kind: TokenKind::Caret,
                 }),
+                '=' if self.iter.clone().next() == Some('=') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::EqualEqual,
+                    })
+                },
+                '!' if self.iter.clone().next() == Some('=') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::ExclamationEqual,
+                    })
+                },
+                '<' if self.iter.clone().next() == Some('=') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::LessEqual,
+                    })
+                },
+                '>' if self.iter.clone().next() == Some('=') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::GreaterEqual,
+                    })
+                },
+                '&' if self.iter.clone().next() == Some('&') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::AmpersandAmpersand,
+                    })
+                },
+                '|' if self.iter.clone().next() == Some('|') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::PipePipe,
+                    })
+                },
+                '|' if self.iter.clone().next() == Some('>') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::PipeGreater,
+                    })
+                },
+                '.' if self.iter.clone().next() == Some('.') => {
+                    self.iter.next();
+                    Some(Token {
+                        lexeme: &string[..2],
+                        kind: TokenKind::DotDot,
+                    })
+                },
+                '∘' => Some(Token {
+                    lexeme: &string[..'∘'.len_utf8()],
+                    kind: TokenKind::Ring,
+                }),
+                '=' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::Equal,
+                }),
+                '<' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::Less,
+                }),
+                '>' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::Greater,
+                }),
+                '!' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::Exclamation,
+                }),
                 ',' => Some(Token {
                     lexeme: &string[..1],
                     kind: TokenKind::Comma,
                 }),
+                ';' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::Semicolon,
+                }),
                 '(' => Some(Token {
                     lexeme: &string[..1],
                     kind: TokenKind::LeftParenthesis,
@@ -154,14 +315,37 @@ impl<'a> Iterator for Tokens<'a> {
                     lexeme: &string[..1],
                     kind: TokenKind::RightParenthesis,
                 }),
+                ':' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::Colon,
+                }),
+                '.' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::Dot,
+                }),
+                '[' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::LeftBracket,
+                }),
+                ']' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::RightBracket,
+                }),
+                '{' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::LeftBrace,
+                }),
+                '}' => Some(Token {
+                    lexeme: &string[..1],
+                    kind: TokenKind::RightBrace,
+                }),
                 _ => {
-                    while self.iter.clone().next().map_or(false, |ch| {
+                    while self.iter.clone().next().is_some_and(|ch| {
                         !(
                             ch.is_whitespace()
-                            || ch.is_digit(10)
+                            || ch.is_ascii_digit()
                             || ch.is_alphabetic()
-                            || (ch == '.' && self.iter.clone().nth(1).map_or(false, |ch| ch.is_digit(10)))
-                            || matches!(ch, '+' | '-' | '*' | '/' | '%' | '^' | ',' | '(' | ')')
+                            || matches!(ch, '+' | '-' | '*' | '/' | '%' | '^' | '=' | '!' | '<' | '>' | '&' | '|' | ',' | ';' | ':' | '.' | '(' | ')' | '[' | ']' | '{' | '}' | '"' | '∘')
                         )
                     }) {
                         self.iter.next();